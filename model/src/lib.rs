@@ -1,11 +1,18 @@
 use ankurah::Model;
 use serde::{Deserialize, Serialize};
 
+pub mod opaque_auth;
+
 #[derive(Model, Debug, Serialize, Deserialize)]
 pub struct User {
     pub display_name: String,
     #[active_type(LWW)]
     pub pub_key: String, // Base64-encoded public key
+    /// Serialized OPAQUE registration envelope, base64-encoded. Empty until the user enrolls a
+    /// password via `UserKeyPairAgent::opaque_register_finish`; absence of a password is not an
+    /// error, it just means this user can only authenticate with their Ed25519 device key.
+    #[active_type(LWW)]
+    pub password_envelope: String,
 }
 
 // Room model - chat rooms
@@ -26,6 +33,104 @@ pub struct Message {
     pub deleted: bool,
 }
 
+/// Links a `User` to a `Room` they've joined. `role` is one of `"member"`, `"moderator"`, or
+/// `"owner"`; `filter_predicate`/`check_read`/`check_event` consult this to scope room/message
+/// visibility and writes to rooms the user actually belongs to.
+#[derive(Model, Debug, Serialize, Deserialize)]
+pub struct RoomMembership {
+    #[active_type(LWW)]
+    pub user: String, // base64 EntityId of the User
+    #[active_type(LWW)]
+    pub room: String, // base64 EntityId of the Room
+    #[active_type(LWW)]
+    pub role: String,
+}
+
+/// A single-use invite minted by `UserKeyPairAgent::mint_invite`. `code_hash` is a digest of the
+/// plaintext code (which is handed to the invitee out-of-band and never stored); `redeemed_by` is
+/// empty until an Anonymous self-registration consumes it. `role`, if non-empty, is granted to the
+/// redeeming user on creation (currently only `"admin"` is recognized; see `MyContextData::Admin`).
+/// `revoked` is a separate flag rather than a sentinel value stuffed into `redeemed_by` (which is
+/// parsed as a base64 `EntityId` by `rehydrate_invites`) so an admin can revoke an invite that was
+/// never redeemed without producing a field that fails to decode on the next restart.
+#[derive(Model, Debug, Serialize, Deserialize)]
+pub struct Invite {
+    pub code_hash: String,
+    pub issued_by: String, // base64 EntityId of the User who minted it
+    #[active_type(LWW)]
+    pub redeemed_by: String, // base64 EntityId of the User who redeemed it, once redeemed
+    pub expires_at: i64, // unix seconds
+    pub role: String, // elevated role granted on redemption, or empty for none
+    #[active_type(LWW)]
+    pub revoked: bool, // set by an admin to retire an invite without redeeming it
+}
+
+/// One leg of an OPAQUE registration or login exchange, relayed through the entity/subscription
+/// system since that's the only transport a browser client has to the server. A client creates
+/// one of these with its outbound protocol message in `request`; the server's background
+/// responder (see `server`'s OPAQUE challenge loop) is the only writer allowed to fill in
+/// `response`, which the client polls for. `kind` is one of `"register-start"`,
+/// `"register-finish"`, `"login-start"`, or `"login-finish"`, matching the four
+/// `UserKeyPairAgent::opaque_*` steps. `handle` is the user-chosen recovery handle these bytes
+/// belong to (the OPAQUE `credential_identifier`).
+#[derive(Model, Debug, Serialize, Deserialize)]
+pub struct OpaqueChallenge {
+    pub handle: String,
+    pub kind: String,
+    pub request: String, // base64 opaque-ke protocol message
+    #[active_type(LWW)]
+    pub response: String, // base64 opaque-ke protocol message, empty until answered
+}
+
+/// Lets a user re-derive their Ed25519 signing-key seed on a new device via an OPAQUE password
+/// login, without the server ever seeing the password or the seed in plaintext. `envelope` is the
+/// OPAQUE registration envelope produced by `UserKeyPairAgent::opaque_register_finish`;
+/// `wrapped_seed`/`wrapped_seed_iv` are the seed AES-GCM-encrypted under the `export_key` that
+/// same OPAQUE flow produces, so decrypting it requires completing an OPAQUE login with the
+/// right password. Looked up by `handle`, a login handle the user chooses at enrollment time (it
+/// need not match anything else about their account).
+#[derive(Model, Debug, Serialize, Deserialize)]
+pub struct IdentityRecovery {
+    pub user: String, // base64 EntityId of the owning User
+    pub handle: String,
+    pub envelope: String, // base64 OPAQUE registration envelope
+    #[active_type(LWW)]
+    pub wrapped_seed: String, // base64 AES-GCM ciphertext of the signing-key seed
+    #[active_type(LWW)]
+    pub wrapped_seed_iv: String, // base64 12-byte AES-GCM IV
+}
+
+/// A request to mint a full-identity bearer token for the requesting user, relayed the same way
+/// `OpaqueChallenge` relays the OPAQUE wire protocol -- a browser client has no other path to
+/// `UserKeyPairAgent::issue_token`, since minting one needs the server's own `token_secret`. A
+/// user may only request a token for themselves (`check_event` enforces `user` matches the
+/// committer); only the server's background responder may fill in `token`.
+#[derive(Model, Debug, Serialize, Deserialize)]
+pub struct TokenIssuance {
+    pub user: String, // base64 EntityId of the requesting user
+    pub scope: i64, // opaque scope byte (0-255), passed through to `issue_token` verbatim
+    pub ttl_secs: i64,
+    #[active_type(LWW)]
+    pub token: String, // base64-encoded bearer token, empty until the server mints it
+}
+
+/// Singleton row persisting server-wide secrets that must survive a restart: the OPAQUE server
+/// setup (restored by `UserKeyPairAgent::rehydrate_opaque_setup`, or minted and persisted here if
+/// none exists yet -- regenerating it would invalidate every stored `User.password_envelope` and
+/// `IdentityRecovery` row, see `AgentVariant::Server::opaque_setup`) and this node's own long-lived
+/// Ed25519 identity (restored by `rehydrate_node_signing_key`; regenerating it would invalidate
+/// every peer's `trust_peer_node` registration of this node, breaking
+/// `validate_received_state`/`validate_received_event` cluster-wide until keys are re-exchanged).
+/// Exactly one row should ever exist. `node_signing_key` is created empty and filled in by
+/// `rehydrate_node_signing_key` on first boot, so it must run after `rehydrate_opaque_setup` has
+/// ensured this row exists.
+#[derive(Model, Debug, Serialize, Deserialize)]
+pub struct ServerConfig {
+    pub opaque_setup: String, // base64-serialized opaque_ke::ServerSetup
+    #[active_type(LWW)]
+    pub node_signing_key: String, // base64-encoded 32-byte Ed25519 seed, empty until minted
+}
+
 // PolicyAgent implementation - must come AFTER models so UserView is available
 mod policy_impl {
     use super::*;
@@ -45,10 +150,36 @@ mod policy_impl {
     };
     use async_trait::async_trait;
     use ed25519_dalek::{Signature, Signer, SigningKey, Verifier, VerifyingKey};
+    use hmac::{Hmac, Mac};
     use once_cell::sync::OnceCell;
+    use opaque_ke::ServerSetup;
+    use rand::RngCore;
+    use sha2::Sha256;
+    use std::collections::{HashMap, HashSet};
     use std::hash::Hash;
-    use std::sync::Arc;
-    use tracing::info;
+    use std::sync::{Arc, Mutex};
+    use std::time::{SystemTime, UNIX_EPOCH};
+    use tracing::{info, Instrument};
+
+    /// Window within which a request's `timestamp_ms` is considered fresh, in either direction.
+    /// Generous enough to tolerate client/server clock skew without opening much of a replay window.
+    const REQUEST_TIMESTAMP_WINDOW_MS: i64 = 30_000;
+
+    /// Upper bound on distinct authenticated users tracked in `seen_nonces` at once. Only
+    /// authenticated callers ever get an entry (see the insertion site in `check_request`), so
+    /// this bounds memory by the cluster's real user count rather than by request volume; it's
+    /// generous enough that legitimate clusters should never hit it, and is only a last-resort
+    /// backstop against the map growing unbounded.
+    const MAX_SEEN_NONCE_USERS: usize = 100_000;
+
+    type HmacSha256 = Hmac<Sha256>;
+
+    fn now_unix_ms() -> i64 {
+        SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map(|d| d.as_millis() as i64)
+            .unwrap_or(0)
+    }
 
     /// ContextData for UserKeyPairAgent
     #[derive(Debug, Clone, PartialEq, Eq, Hash)]
@@ -57,13 +188,56 @@ mod policy_impl {
         User(proto::EntityId),
         /// System/root context - only constructable locally, never from AuthData
         Root,
-        /// Anonymous/unauthenticated context - for user self-registration
-        Anonymous,
+        /// Anonymous/unauthenticated context - for user self-registration. `invite_code` carries
+        /// the plaintext invite code (see `Invite`/`mint_invite`) required to create a `User`.
+        Anonymous { invite_code: Option<String> },
+        /// Elevated context for managing invitations, granted to a `User` once
+        /// `UserKeyPairAgent::grant_admin` has recorded them as an admin (e.g. by redeeming an
+        /// invite whose `role` was `"admin"`). Like `User`, this is never trusted at face value:
+        /// `check_request` only ever produces it after re-verifying the request's signature and
+        /// checking the signer against the admin set itself.
+        Admin(proto::EntityId),
+        /// Delegated, time-boxed access bound to a specific set of `Scopes` rather than full
+        /// account privileges -- minted by `UserKeyPairAgent::issue_scoped_token` from a
+        /// `Root`/`Admin` context for bot accounts or shareable read-only links. Like
+        /// `User`/`Admin`, never trusted at face value: `check_request` only produces this after
+        /// verifying the token's HMAC and expiry.
+        Scoped {
+            user_id: proto::EntityId,
+            scopes: Scopes,
+            expires_at: i64, // unix seconds
+        },
     }
 
     #[async_trait]
     impl ContextDataTrait for MyContextData {}
 
+    /// Capability bits a `MyContextData::Scoped` token can be restricted to. Packed into a single
+    /// byte (the same shape `issue_token`'s opaque scope byte already reserved, see
+    /// `TOKEN_PAYLOAD_LEN`), but unlike that byte, these are actually enforced by
+    /// `check_event`/`check_read`/`filter_predicate`.
+    #[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+    pub struct Scopes(pub u8);
+
+    impl Scopes {
+        pub const NONE: Scopes = Scopes(0);
+        pub const READ_ROOM: Scopes = Scopes(0b0001);
+        pub const WRITE_MESSAGE: Scopes = Scopes(0b0010);
+        pub const CREATE_ROOM: Scopes = Scopes(0b0100);
+        pub const MANAGE_USERS: Scopes = Scopes(0b1000);
+
+        pub fn contains(&self, capability: Scopes) -> bool {
+            self.0 & capability.0 == capability.0
+        }
+    }
+
+    impl std::ops::BitOr for Scopes {
+        type Output = Scopes;
+        fn bitor(self, rhs: Scopes) -> Scopes {
+            Scopes(self.0 | rhs.0)
+        }
+    }
+
     /// PolicyAgent that uses Ed25519 keypairs for request signing
     #[derive(Clone)]
     pub struct UserKeyPairAgent {
@@ -74,12 +248,136 @@ mod policy_impl {
     enum AgentVariant {
         /// Client variant - holds private key for signing
         Client { signing_key: Arc<SigningKey> },
+        /// Client variant enrolled via OPAQUE password login rather than a locally-stored key.
+        /// `signing_key` is deterministically derived from the OPAQUE `export_key`
+        /// (see `opaque_auth::derive_signing_key`), so it signs requests exactly like `Client`.
+        ClientPassword { signing_key: Arc<SigningKey> },
+        /// Client variant holding a bearer token minted by `UserKeyPairAgent::issue_token` after
+        /// an initial Ed25519/OPAQUE login, so subsequent requests cost an HMAC check on the
+        /// server instead of a signature verify plus a `UserView` fetch.
+        Token {
+            user_id: proto::EntityId,
+            token: Vec<u8>,
+        },
+        /// Client variant holding a capability-scoped bearer token minted by
+        /// `UserKeyPairAgent::issue_scoped_token`. Unlike `Token`, this can only ever assert
+        /// `MyContextData::Scoped` -- there's no underlying full-identity credential behind it,
+        /// which is the point: delegated/bot access and shareable read-only links.
+        ScopedToken { token: Vec<u8> },
         /// Server variant - holds root context for system queries (lazy-initialized)
         Server {
             root_context: Arc<OnceCell<Context>>,
+            /// Nonces seen per-user within the replay window, keyed by user id. Only recorded
+            /// once `check_request` has verified the signed request really came from that user
+            /// (see the comment at the insertion site), so an unauthenticated caller can't grow
+            /// this map by sending garbage `user_id`s. Entries older than
+            /// `REQUEST_TIMESTAMP_WINDOW_MS` are evicted lazily on each check, both per-user and,
+            /// once the map exceeds `MAX_SEEN_NONCE_USERS`, across the whole map.
+            seen_nonces: Arc<Mutex<HashMap<proto::EntityId, Vec<(i64, u128)>>>>,
+            /// OPAQUE server setup (private key + OPRF seed), restored from the persisted
+            /// `ServerConfig` row by `rehydrate_opaque_setup` (or minted and persisted there if
+            /// none exists yet). Regenerating it invalidates any `password_envelope`/
+            /// `IdentityRecovery` row stored under a previous run, so it must never be
+            /// re-generated once a `ServerConfig` row exists.
+            opaque_setup: Arc<OnceCell<ServerSetup<opaque_auth::TemplateCipherSuite>>>,
+            /// HMAC key for minting/verifying bearer tokens, generated once per process. Rotating
+            /// it (by restarting the server) invalidates every outstanding token.
+            token_secret: Arc<[u8; 32]>,
+            /// In-memory mirror of `RoomMembership` (user -> room -> role), keyed so
+            /// `filter_predicate`/`check_read` can enforce per-room ACLs without a storage
+            /// round-trip from those synchronous hooks. Kept current by `check_event` whenever a
+            /// `RoomMembership` is written.
+            memberships: Arc<Mutex<HashMap<proto::EntityId, HashMap<proto::EntityId, String>>>>,
+            /// In-memory invite ledger keyed by `code_hash`, mirroring `Invite` so redemption can
+            /// be checked-and-marked atomically from the synchronous `check_event` hook.
+            invites: Arc<Mutex<HashMap<String, InviteRecord>>>,
+            /// Users granted `MyContextData::Admin`, populated by `grant_admin` (typically when a
+            /// `role: "admin"` invite is redeemed). Consulted by `check_request` so a client can
+            /// never assert Admin over the wire without the signer actually being in this set.
+            admins: Arc<Mutex<HashSet<proto::EntityId>>>,
+            /// This node's own Ed25519 identity, used by `attest_state` to sign state handed to
+            /// other nodes so they have a verifiable chain of custody for it. Restored from (or
+            /// minted and persisted into) the `ServerConfig` row by `rehydrate_node_signing_key`;
+            /// regenerating it invalidates every peer's `trust_peer_node` registration of this
+            /// node, so it must never be re-generated once a `ServerConfig` row exists.
+            node_signing_key: Arc<OnceCell<SigningKey>>,
+            /// Verifying keys of peer nodes we accept attestations from, keyed by the peer's
+            /// `EntityId` (the `received_from_node` passed to `validate_received_state`/
+            /// `validate_received_event`). Configured via `trust_peer_node`; nodes not in this set
+            /// are rejected rather than implicitly trusted.
+            trusted_peers: Arc<Mutex<HashMap<proto::EntityId, VerifyingKey>>>,
+            /// In-flight OPAQUE login server state, keyed by recovery handle, between the
+            /// `login-start` challenge (which produces this state) and `login-finish` (which
+            /// consumes it). Unlike registration, `ServerLogin` carries secret ephemeral key
+            /// material that can't be recomputed from `request`/`response` alone, so it has to be
+            /// held somewhere across the two challenge round trips.
+            opaque_login_sessions: Arc<Mutex<HashMap<String, opaque_ke::ServerLogin<opaque_auth::TemplateCipherSuite>>>>,
+            /// Notified by `redeem_invite` whenever it redeems a `role: "admin"` invite, so
+            /// `flush_redeemed_invites` can be woken immediately instead of waiting out the
+            /// periodic poll interval. A crash between an admin grant landing in the in-memory
+            /// ledger and its `redeemed_by` reaching the persisted `Invite` row would let
+            /// `rehydrate_invites` see it as still-unredeemed and let someone else redeem (and
+            /// re-grant admin from) the same invite after restart -- narrowing that window matters
+            /// specifically for this grant, not for an ordinary member invite.
+            admin_grant_notify: Arc<tokio::sync::Notify>,
         },
     }
 
+    struct InviteRecord {
+        expires_at: i64,
+        redeemed: bool,
+        role: String,
+        /// Who redeemed this invite, if anyone. Set by `redeem_invite`; `flush_redeemed_invites`
+        /// consults this to persist the redemption to the `Invite` entity's `redeemed_by` field.
+        redeemed_by: Option<proto::EntityId>,
+    }
+
+    fn invite_code_hash(code: &str) -> String {
+        base64::encode(Sha256::digest(code.as_bytes()))
+    }
+
+    /// Decode a scoped token's `user_id`/`scopes`/`expires_at` claims without verifying its HMAC
+    /// (only the server's `token_secret` can do that). Lets a holder build the matching
+    /// `MyContextData::Scoped` locally; forging a claim here buys nothing since `check_request`
+    /// re-verifies the signature on every request.
+    pub fn decode_scoped_token_claims(token: &[u8]) -> Result<(proto::EntityId, Scopes, i64), &'static str> {
+        if !token.starts_with(SCOPED_TOKEN_MAGIC) {
+            return Err("Not a scoped token");
+        }
+        let body = &token[SCOPED_TOKEN_MAGIC.len()..];
+        if body.len() != TOKEN_PAYLOAD_LEN + TOKEN_MAC_LEN {
+            return Err("Malformed scoped token");
+        }
+        let payload = &body[..TOKEN_PAYLOAD_LEN];
+        let user_id_bytes: [u8; 16] = payload[..16].try_into().map_err(|_| "Malformed scoped token")?;
+        let expiry_bytes: [u8; 8] = payload[16..24].try_into().map_err(|_| "Malformed scoped token")?;
+        Ok((
+            proto::EntityId::from_bytes(user_id_bytes),
+            Scopes(payload[24]),
+            i64::from_be_bytes(expiry_bytes),
+        ))
+    }
+
+    const TOKEN_MAGIC: &[u8; 4] = b"TOK1";
+    const TOKEN_PAYLOAD_LEN: usize = 16 /* user id */ + 8 /* expiry unix secs */ + 1 /* scope bits */;
+    const TOKEN_MAC_LEN: usize = 32;
+
+    /// Same payload shape as `TOKEN_MAGIC`, but the scope byte is enforced by `check_event`/
+    /// `check_read`/`filter_predicate` rather than left opaque -- see `issue_scoped_token`.
+    const SCOPED_TOKEN_MAGIC: &[u8; 4] = b"SCP1";
+
+    const ANONYMOUS_MAGIC: &[u8; 4] = b"ANO1";
+
+    /// Builds the AuthData for an Anonymous context: a magic tag followed by the UTF-8 invite
+    /// code, if any, so `check_request` can recognize it regardless of the code's length.
+    fn anonymous_auth_data(invite_code: &Option<String>) -> Vec<u8> {
+        let mut bytes = ANONYMOUS_MAGIC.to_vec();
+        if let Some(code) = invite_code {
+            bytes.extend_from_slice(code.as_bytes());
+        }
+        bytes
+    }
+
     impl UserKeyPairAgent {
         /// Create a client agent with a signing key
         pub fn new_client(signing_key: SigningKey) -> Self {
@@ -90,28 +388,760 @@ mod policy_impl {
             }
         }
 
+        /// Create a client agent enrolled via OPAQUE password login (see `opaque_auth`)
+        pub fn new_client_password(signing_key: SigningKey) -> Self {
+            Self {
+                variant: AgentVariant::ClientPassword {
+                    signing_key: Arc::new(signing_key),
+                },
+            }
+        }
+
+        /// Create a client agent holding a bearer token minted by `issue_token`
+        pub fn new_token(user_id: proto::EntityId, token: Vec<u8>) -> Self {
+            Self {
+                variant: AgentVariant::Token { user_id, token },
+            }
+        }
+
+        /// Create a client agent holding a capability-scoped token minted by `issue_scoped_token`
+        pub fn new_scoped_token(token: Vec<u8>) -> Self {
+            Self {
+                variant: AgentVariant::ScopedToken { token },
+            }
+        }
+
         /// Create a server agent (root context will be lazily initialized)
         pub fn new_server() -> Self {
+            let mut token_secret = [0u8; 32];
+            rand::rngs::OsRng.fill_bytes(&mut token_secret);
             Self {
                 variant: AgentVariant::Server {
                     root_context: Arc::new(OnceCell::new()),
+                    seen_nonces: Arc::new(Mutex::new(HashMap::new())),
+                    opaque_setup: Arc::new(OnceCell::new()),
+                    token_secret: Arc::new(token_secret),
+                    memberships: Arc::new(Mutex::new(HashMap::new())),
+                    invites: Arc::new(Mutex::new(HashMap::new())),
+                    admins: Arc::new(Mutex::new(HashSet::new())),
+                    node_signing_key: Arc::new(OnceCell::new()),
+                    trusted_peers: Arc::new(Mutex::new(HashMap::new())),
+                    opaque_login_sessions: Arc::new(Mutex::new(HashMap::new())),
+                    admin_grant_notify: Arc::new(tokio::sync::Notify::new()),
+                },
+            }
+        }
+
+        /// This node's own Ed25519 identity, restored (or minted and persisted) by
+        /// `rehydrate_node_signing_key`.
+        fn get_node_signing_key(&self) -> &SigningKey {
+            if let AgentVariant::Server { node_signing_key, .. } = &self.variant {
+                node_signing_key
+                    .get()
+                    .expect("Node signing key not initialized - call rehydrate_node_signing_key first")
+            } else {
+                panic!("get_node_signing_key called on non-server variant")
+            }
+        }
+
+        /// This node's public verifying key, to be shared with peers so they can `trust_peer_node`.
+        pub fn node_verifying_key(&self) -> VerifyingKey {
+            self.get_node_signing_key().verifying_key()
+        }
+
+        /// Register a peer node's verifying key as trusted, so attestations it relays pass
+        /// `validate_received_state`/`validate_received_event`.
+        pub fn trust_peer_node(&self, node_id: proto::EntityId, verifying_key: VerifyingKey) {
+            let AgentVariant::Server { trusted_peers, .. } = &self.variant else {
+                panic!("trust_peer_node called on non-server variant")
+            };
+            trusted_peers.lock().unwrap().insert(node_id, verifying_key);
+        }
+
+        /// Verify an attestation (32-byte verifying key || 64-byte signature) was produced by
+        /// `received_from_node`'s trusted key over the SHA-256 digest of `digest_input`.
+        fn verify_peer_attestation(
+            &self,
+            received_from_node: &proto::EntityId,
+            attestation: &Option<proto::Attestation>,
+            digest_input: &[u8],
+        ) -> Result<(), AccessDenied> {
+            let AgentVariant::Server { trusted_peers, .. } = &self.variant else {
+                return Ok(());
+            };
+
+            let attestation = attestation
+                .as_ref()
+                .ok_or(AccessDenied::ByPolicy("Missing attestation from peer node"))?;
+
+            let trusted_peers = trusted_peers.lock().unwrap();
+            let trusted_key = trusted_peers
+                .get(received_from_node)
+                .ok_or(AccessDenied::ByPolicy("Unknown or untrusted peer node"))?;
+
+            if attestation.0.len() != 32 + 64 {
+                return Err(AccessDenied::ByPolicy("Malformed attestation"));
+            }
+            let (key_bytes, signature_bytes) = attestation.0.split_at(32);
+
+            let embedded_key_bytes: [u8; 32] = key_bytes
+                .try_into()
+                .map_err(|_| AccessDenied::ByPolicy("Malformed attestation"))?;
+            let embedded_key = VerifyingKey::from_bytes(&embedded_key_bytes)
+                .map_err(|_| AccessDenied::ByPolicy("Malformed attestation"))?;
+            if &embedded_key != trusted_key {
+                return Err(AccessDenied::ByPolicy(
+                    "Attestation key does not match trusted peer",
+                ));
+            }
+
+            let signature_bytes: [u8; 64] = signature_bytes
+                .try_into()
+                .map_err(|_| AccessDenied::ByPolicy("Malformed attestation"))?;
+            let signature = Signature::from_bytes(&signature_bytes);
+
+            let digest = Sha256::digest(digest_input);
+            trusted_key
+                .verify(&digest, &signature)
+                .map_err(|_| AccessDenied::ByPolicy("Invalid attestation signature"))
+        }
+
+        /// Mint a single-use invite good for `ttl_secs`, persisting it as an `Invite` entity (via
+        /// the root context) and registering it in the in-memory ledger `check_event` consults.
+        /// `role`, if non-empty, is granted to the user created by redeeming it (see
+        /// `MyContextData::Admin`). Returns the plaintext code to hand to the invitee
+        /// out-of-band; the server never needs to see it again.
+        pub async fn mint_invite(
+            &self,
+            issued_by: proto::EntityId,
+            ttl_secs: i64,
+            role: String,
+        ) -> anyhow::Result<String> {
+            let AgentVariant::Server { invites, .. } = &self.variant else {
+                anyhow::bail!("mint_invite called on non-server variant");
+            };
+
+            let mut code_bytes = [0u8; 16];
+            rand::rngs::OsRng.fill_bytes(&mut code_bytes);
+            let code = base64::encode(code_bytes);
+            let code_hash = invite_code_hash(&code);
+            let expires_at = now_unix_ms() / 1000 + ttl_secs;
+
+            let root_context = self.get_root_context();
+            let trx = root_context.begin();
+            trx.create(&Invite {
+                code_hash: code_hash.clone(),
+                issued_by: issued_by.to_base64(),
+                redeemed_by: String::new(),
+                expires_at,
+                role: role.clone(),
+                revoked: false,
+            })
+            .await?;
+            trx.commit()
+                .instrument(tracing::info_span!("transaction.commit", collection = "invite"))
+                .await?;
+
+            invites.lock().unwrap().insert(
+                code_hash,
+                InviteRecord {
+                    expires_at,
+                    redeemed: false,
+                    role,
+                    redeemed_by: None,
                 },
+            );
+
+            Ok(code)
+        }
+
+        /// Atomically check-and-mark an invite as redeemed, returning the role (if any) it
+        /// grants. The authoritative record is the in-memory ledger (so this can run from the
+        /// synchronous `check_event` hook); `redeemed_by` is persisted back to the `Invite` entity
+        /// out-of-band by `flush_redeemed_invites`, since a durable write can't happen from this
+        /// synchronous call.
+        fn redeem_invite(&self, code: &str, redeemed_by: proto::EntityId) -> Result<String, AccessDenied> {
+            let AgentVariant::Server { invites, admin_grant_notify, .. } = &self.variant else {
+                return Err(AccessDenied::ByPolicy("invite required"));
+            };
+
+            let role = {
+                let code_hash = invite_code_hash(code);
+                let mut invites = invites.lock().unwrap();
+                let record = invites
+                    .get_mut(&code_hash)
+                    .ok_or(AccessDenied::ByPolicy("invite required"))?;
+
+                if record.redeemed || now_unix_ms() / 1000 > record.expires_at {
+                    return Err(AccessDenied::ByPolicy("invite required"));
+                }
+
+                record.redeemed = true;
+                record.redeemed_by = Some(redeemed_by);
+                record.role.clone()
+            };
+
+            if role == "admin" {
+                // Wake `flush_redeemed_invites_loop` right away rather than letting it find this
+                // on its next periodic poll -- see `admin_grant_notify`'s doc comment for why this
+                // grant specifically can't tolerate that window.
+                admin_grant_notify.notify_one();
+            }
+
+            Ok(role)
+        }
+
+        /// Mark an invite revoked in the in-memory ledger, so it can no longer be redeemed. Called
+        /// from `check_event` whenever an Admin context writes to the `Invite` collection.
+        fn revoke_invite_record(&self, code_hash: &str) {
+            if let AgentVariant::Server { invites, .. } = &self.variant {
+                if let Some(record) = invites.lock().unwrap().get_mut(code_hash) {
+                    record.redeemed = true;
+                }
             }
         }
 
+        /// Reloads the in-memory invite ledger from persisted `Invite` rows. Without this a
+        /// restart starts the ledger empty, so `redeem_invite` rejects every still-outstanding
+        /// invite ("invite required") and self-registration breaks until a fresh one is minted.
+        pub async fn rehydrate_invites(&self) -> anyhow::Result<()> {
+            let AgentVariant::Server { invites, .. } = &self.variant else {
+                anyhow::bail!("rehydrate_invites called on non-server variant");
+            };
+
+            let root_context = self.get_root_context();
+            let mut loaded = HashMap::new();
+            for invite in root_context.fetch::<InviteView>("true").await? {
+                let redeemed_by_str = invite.redeemed_by()?;
+                let redeemed_by = if redeemed_by_str.is_empty() {
+                    None
+                } else {
+                    Some(proto::EntityId::from_base64(&redeemed_by_str)?)
+                };
+                let revoked = invite.revoked()?;
+                loaded.insert(
+                    invite.code_hash()?,
+                    InviteRecord {
+                        expires_at: invite.expires_at()?,
+                        redeemed: redeemed_by.is_some() || revoked,
+                        role: invite.role()?,
+                        redeemed_by,
+                    },
+                );
+            }
+            *invites.lock().unwrap() = loaded;
+
+            Ok(())
+        }
+
+        /// Reloads the admin set from the durable source of admin grants: redeemed `role: "admin"`
+        /// invites. Must run after `rehydrate_invites` (it reads that ledger rather than
+        /// re-fetching `Invite` rows itself). Without this, `admins` starts empty on every
+        /// restart and every previously-granted admin silently loses `MyContextData::Admin`.
+        pub async fn rehydrate_admins(&self) -> anyhow::Result<()> {
+            let AgentVariant::Server { invites, .. } = &self.variant else {
+                anyhow::bail!("rehydrate_admins called on non-server variant");
+            };
+
+            let admin_grants: Vec<proto::EntityId> = invites
+                .lock()
+                .unwrap()
+                .values()
+                .filter(|record| record.role == "admin")
+                .filter_map(|record| record.redeemed_by)
+                .collect();
+
+            for user_id in admin_grants {
+                self.grant_admin(user_id);
+            }
+
+            Ok(())
+        }
+
+        /// Resolves as soon as `redeem_invite` redeems a `role: "admin"` invite, so
+        /// `flush_redeemed_invites_loop` can persist that redemption immediately instead of
+        /// waiting out its normal poll interval. Meant to be raced against the interval tick with
+        /// `tokio::select!`.
+        pub async fn wait_for_admin_grant(&self) {
+            if let AgentVariant::Server { admin_grant_notify, .. } = &self.variant {
+                admin_grant_notify.notified().await;
+            } else {
+                std::future::pending::<()>().await;
+            }
+        }
+
+        /// Persists any redemption recorded in the in-memory ledger (via `redeem_invite`) that
+        /// hasn't reached the `Invite` entity's `redeemed_by` field yet. `check_event` can only
+        /// update the synchronous ledger, so this is the out-of-band write that makes redemption
+        /// durable (and, combined with `rehydrate_invites`, keeps a single-use invite from being
+        /// re-redeemable across a restart). Meant to be polled periodically from `main`, and woken
+        /// early via `wait_for_admin_grant` whenever an admin grant needs to beat that window.
+        pub async fn flush_redeemed_invites(&self) -> anyhow::Result<()> {
+            let AgentVariant::Server { invites, .. } = &self.variant else {
+                anyhow::bail!("flush_redeemed_invites called on non-server variant");
+            };
+
+            let pending: Vec<(String, proto::EntityId)> = invites
+                .lock()
+                .unwrap()
+                .iter()
+                .filter_map(|(code_hash, record)| Some((code_hash.clone(), record.redeemed_by?)))
+                .collect();
+
+            let root_context = self.get_root_context();
+            for (code_hash, redeemed_by) in pending {
+                let matches = root_context
+                    .fetch::<InviteView>(&format!("code_hash = '{}'", code_hash))
+                    .await?;
+                let Some(invite) = matches.into_iter().next() else { continue };
+                if !invite.redeemed_by()?.is_empty() {
+                    continue; // already persisted
+                }
+
+                let trx = root_context.begin();
+                let invite_mut = trx.edit(&invite).await?;
+                invite_mut.redeemed_by().set(&redeemed_by.to_base64())?;
+                trx.commit()
+                    .instrument(tracing::info_span!("transaction.commit", collection = "invite"))
+                    .await?;
+            }
+
+            Ok(())
+        }
+
+        /// Record `user_id` as an admin, granting `MyContextData::Admin` to future requests it
+        /// signs. Typically called from `check_event` when a `role: "admin"` invite is redeemed.
+        fn grant_admin(&self, user_id: proto::EntityId) {
+            if let AgentVariant::Server { admins, .. } = &self.variant {
+                admins.lock().unwrap().insert(user_id);
+            }
+        }
+
+        /// Whether `user_id` is currently recorded as an admin (see `grant_admin`).
+        fn is_admin(&self, user_id: &proto::EntityId) -> bool {
+            let AgentVariant::Server { admins, .. } = &self.variant else {
+                return false;
+            };
+            admins.lock().unwrap().contains(user_id)
+        }
+
+        /// The context a freshly-verified `user_id` should be granted: `Admin` if they're in the
+        /// admin set, `User` otherwise. Used by `check_request` for both the Ed25519 and bearer
+        /// token paths so admin status is decided solely by the admin set, never by which variant
+        /// a client happened to ask for.
+        fn context_for_verified_user(&self, user_id: proto::EntityId) -> MyContextData {
+            if self.is_admin(&user_id) {
+                MyContextData::Admin(user_id)
+            } else {
+                MyContextData::User(user_id)
+            }
+        }
+
+        /// Rooms `user_id` currently belongs to, per the in-memory membership cache.
+        fn member_rooms(&self, user_id: &proto::EntityId) -> Vec<proto::EntityId> {
+            let AgentVariant::Server { memberships, .. } = &self.variant else {
+                return Vec::new();
+            };
+            memberships
+                .lock()
+                .unwrap()
+                .get(user_id)
+                .map(|rooms| rooms.keys().copied().collect())
+                .unwrap_or_default()
+        }
+
+        /// Whether any user already holds a `RoomMembership` in `room_id`, per the in-memory
+        /// cache. Lets a freshly created room's first membership be self-granted as `owner` (see
+        /// `check_event`'s `roommembership` handling) without letting anyone self-promote once a
+        /// room already has an owner/moderator structure.
+        fn room_has_any_member(&self, room_id: &proto::EntityId) -> bool {
+            let AgentVariant::Server { memberships, .. } = &self.variant else {
+                return false;
+            };
+            memberships
+                .lock()
+                .unwrap()
+                .values()
+                .any(|rooms| rooms.contains_key(room_id))
+        }
+
+        /// Role `user_id` holds in `room_id` ("member"/"moderator"/"owner"), if any.
+        fn member_role(&self, user_id: &proto::EntityId, room_id: &proto::EntityId) -> Option<String> {
+            let AgentVariant::Server { memberships, .. } = &self.variant else {
+                return None;
+            };
+            memberships
+                .lock()
+                .unwrap()
+                .get(user_id)
+                .and_then(|rooms| rooms.get(room_id))
+                .cloned()
+        }
+
+        /// Builds the `<id_field> IN (...)` predicate that restricts a fetch to `allowed_rooms`.
+        /// A user in zero rooms must still get a predicate that matches nothing -- `Predicate::In`
+        /// with an empty list depends on however the ankql evaluator happens to treat `IN ()`, which
+        /// isn't something this crate controls or has verified, so the empty case substitutes a
+        /// single literal no real room id can ever equal instead of relying on that.
+        fn room_membership_predicate(
+            id_field: &str,
+            allowed_rooms: &[proto::EntityId],
+        ) -> ankurah::ankql::ast::Predicate {
+            use ankurah::ankql::ast::{Expr, Identifier, Literal};
+
+            let values = if allowed_rooms.is_empty() {
+                vec![Expr::Literal(Literal::String(String::new()))]
+            } else {
+                allowed_rooms
+                    .iter()
+                    .map(|room_id| Expr::Literal(Literal::String(room_id.to_base64())))
+                    .collect()
+            };
+
+            ankurah::ankql::ast::Predicate::In(
+                Expr::Identifier(Identifier::Property(id_field.to_string())),
+                values,
+            )
+        }
+
+        /// Record (or update) a `RoomMembership` in the in-memory cache.
+        fn record_membership(&self, user_id: proto::EntityId, room_id: proto::EntityId, role: String) {
+            if let AgentVariant::Server { memberships, .. } = &self.variant {
+                memberships
+                    .lock()
+                    .unwrap()
+                    .entry(user_id)
+                    .or_default()
+                    .insert(room_id, role);
+            }
+        }
+
+        /// Mint a bearer token binding `user_id` for `ttl_secs`, carrying an opaque `scope` byte
+        /// the caller interprets. Verifying it back (`check_request`) is a single HMAC check with
+        /// no storage lookup, unlike the Ed25519 path which fetches `UserView` on every request.
+        pub fn issue_token(&self, user_id: proto::EntityId, ttl_secs: i64, scope: u8) -> Vec<u8> {
+            let AgentVariant::Server { token_secret, .. } = &self.variant else {
+                panic!("issue_token called on non-server variant")
+            };
+
+            let expiry_secs = now_unix_ms() / 1000 + ttl_secs;
+            let mut payload = Vec::with_capacity(TOKEN_PAYLOAD_LEN);
+            payload.extend_from_slice(&user_id.to_bytes());
+            payload.extend_from_slice(&expiry_secs.to_be_bytes());
+            payload.push(scope);
+
+            let mut mac = HmacSha256::new_from_slice(token_secret.as_ref())
+                .expect("HMAC accepts a key of any length");
+            mac.update(&payload);
+            let tag = mac.finalize().into_bytes();
+
+            let mut token = Vec::with_capacity(TOKEN_MAGIC.len() + TOKEN_PAYLOAD_LEN + TOKEN_MAC_LEN);
+            token.extend_from_slice(TOKEN_MAGIC);
+            token.extend_from_slice(&payload);
+            token.extend_from_slice(&tag);
+            token
+        }
+
+        /// Validate a bearer token produced by `issue_token`, returning the user it authenticates.
+        fn verify_token(&self, bytes: &[u8]) -> Result<proto::EntityId, ValidationError> {
+            let AgentVariant::Server { token_secret, .. } = &self.variant else {
+                return Err(ValidationError::ValidationFailed(
+                    "Client cannot validate requests".to_string(),
+                ));
+            };
+
+            let body = &bytes[TOKEN_MAGIC.len()..];
+            if body.len() != TOKEN_PAYLOAD_LEN + TOKEN_MAC_LEN {
+                return Err(ValidationError::ValidationFailed(
+                    "Malformed token".to_string(),
+                ));
+            }
+            let (payload, tag) = body.split_at(TOKEN_PAYLOAD_LEN);
+
+            let mut mac = HmacSha256::new_from_slice(token_secret.as_ref())
+                .expect("HMAC accepts a key of any length");
+            mac.update(payload);
+            mac.verify_slice(tag)
+                .map_err(|_| ValidationError::ValidationFailed("Invalid token signature".to_string()))?;
+
+            let user_id_bytes: [u8; 16] = payload[..16].try_into().unwrap();
+            let expiry_bytes: [u8; 8] = payload[16..24].try_into().unwrap();
+            let expiry_secs = i64::from_be_bytes(expiry_bytes);
+            if now_unix_ms() / 1000 > expiry_secs {
+                return Err(ValidationError::ValidationFailed("Token expired".to_string()));
+            }
+
+            Ok(proto::EntityId::from_bytes(user_id_bytes))
+        }
+
+        /// Mint a capability-scoped bearer token good for `ttl_secs`, authenticating as `user_id`
+        /// but restricted to `scopes` rather than full account access. Intended to be called from
+        /// a `Root`/`Admin` context, e.g. to delegate a bot account or mint a shareable read-only
+        /// link. Unlike `issue_token` (whose scope byte is opaque and left for the caller to
+        /// interpret), this one's scopes are enforced server-side via `MyContextData::Scoped`.
+        pub fn issue_scoped_token(&self, user_id: proto::EntityId, scopes: Scopes, ttl_secs: i64) -> Vec<u8> {
+            let AgentVariant::Server { token_secret, .. } = &self.variant else {
+                panic!("issue_scoped_token called on non-server variant")
+            };
+
+            let expires_at = now_unix_ms() / 1000 + ttl_secs;
+            let mut payload = Vec::with_capacity(TOKEN_PAYLOAD_LEN);
+            payload.extend_from_slice(&user_id.to_bytes());
+            payload.extend_from_slice(&expires_at.to_be_bytes());
+            payload.push(scopes.0);
+
+            let mut mac = HmacSha256::new_from_slice(token_secret.as_ref())
+                .expect("HMAC accepts a key of any length");
+            mac.update(&payload);
+            let tag = mac.finalize().into_bytes();
+
+            let mut token = Vec::with_capacity(SCOPED_TOKEN_MAGIC.len() + TOKEN_PAYLOAD_LEN + TOKEN_MAC_LEN);
+            token.extend_from_slice(SCOPED_TOKEN_MAGIC);
+            token.extend_from_slice(&payload);
+            token.extend_from_slice(&tag);
+            token
+        }
+
+        /// Validate a scoped token produced by `issue_scoped_token`, returning the claims it carries.
+        fn verify_scoped_token(&self, bytes: &[u8]) -> Result<(proto::EntityId, Scopes, i64), ValidationError> {
+            let AgentVariant::Server { token_secret, .. } = &self.variant else {
+                return Err(ValidationError::ValidationFailed(
+                    "Client cannot validate requests".to_string(),
+                ));
+            };
+
+            let body = &bytes[SCOPED_TOKEN_MAGIC.len()..];
+            if body.len() != TOKEN_PAYLOAD_LEN + TOKEN_MAC_LEN {
+                return Err(ValidationError::ValidationFailed(
+                    "Malformed scoped token".to_string(),
+                ));
+            }
+            let (payload, tag) = body.split_at(TOKEN_PAYLOAD_LEN);
+
+            let mut mac = HmacSha256::new_from_slice(token_secret.as_ref())
+                .expect("HMAC accepts a key of any length");
+            mac.update(payload);
+            mac.verify_slice(tag).map_err(|_| {
+                ValidationError::ValidationFailed("Invalid scoped token signature".to_string())
+            })?;
+
+            let user_id_bytes: [u8; 16] = payload[..16].try_into().unwrap();
+            let expiry_bytes: [u8; 8] = payload[16..24].try_into().unwrap();
+            let expires_at = i64::from_be_bytes(expiry_bytes);
+            if now_unix_ms() / 1000 > expires_at {
+                return Err(ValidationError::ValidationFailed("Scoped token expired".to_string()));
+            }
+
+            Ok((proto::EntityId::from_bytes(user_id_bytes), Scopes(payload[24]), expires_at))
+        }
+
+        /// OPAQUE server setup, restored (or minted and persisted) by `rehydrate_opaque_setup`.
+        fn get_opaque_setup(&self) -> &ServerSetup<opaque_auth::TemplateCipherSuite> {
+            if let AgentVariant::Server { opaque_setup, .. } = &self.variant {
+                opaque_setup
+                    .get()
+                    .expect("OPAQUE server setup not initialized - call rehydrate_opaque_setup first")
+            } else {
+                panic!("get_opaque_setup called on non-server variant")
+            }
+        }
+
+        /// Server-side OPAQUE registration, step 1: evaluate the OPRF over the client's blinded
+        /// password and return the response the client needs to derive its envelope.
+        pub fn opaque_register_start(
+            &self,
+            request: opaque_ke::RegistrationRequest<opaque_auth::TemplateCipherSuite>,
+            credential_identifier: &[u8],
+        ) -> Result<
+            opaque_ke::RegistrationResponse<opaque_auth::TemplateCipherSuite>,
+            opaque_ke::errors::ProtocolError,
+        > {
+            opaque_auth::register_server_start(self.get_opaque_setup(), request, credential_identifier)
+        }
+
+        /// Server-side OPAQUE registration, step 2: finish the uploaded envelope and return its
+        /// serialized bytes. Nothing is persisted here; the caller stores them (base64-encoded,
+        /// e.g. as a `User.password_envelope` or `IdentityRecovery.envelope`).
+        pub fn opaque_register_finish(
+            &self,
+            upload: opaque_ke::RegistrationUpload<opaque_auth::TemplateCipherSuite>,
+        ) -> Result<Vec<u8>, opaque_ke::errors::ProtocolError> {
+            Ok(opaque_auth::register_server_finish(upload)?.serialize().to_vec())
+        }
+
+        /// Server-side OPAQUE login, step 1. `password_file` is the stored `password_envelope`
+        /// deserialized by the caller; `None` still produces a response so a probing client can't
+        /// distinguish "no such user" from "wrong password".
+        pub fn opaque_login_start(
+            &self,
+            password_file: Option<opaque_ke::ServerRegistration<opaque_auth::TemplateCipherSuite>>,
+            credential_identifier: &[u8],
+            request: opaque_ke::CredentialRequest<opaque_auth::TemplateCipherSuite>,
+        ) -> Result<
+            opaque_ke::ServerLoginStartResult<opaque_auth::TemplateCipherSuite>,
+            opaque_ke::errors::ProtocolError,
+        > {
+            opaque_auth::login_server_start(
+                self.get_opaque_setup(),
+                password_file,
+                credential_identifier,
+                request,
+            )
+        }
+
+        /// Server-side OPAQUE login, step 2: verify the finalization. On success the returned
+        /// `session_key` matches what the client derives. No context is minted here or by the
+        /// server's OPAQUE responder, which only answers the challenge to signal completion.
+        pub fn opaque_login_finish(
+            &self,
+            state: opaque_ke::ServerLogin<opaque_auth::TemplateCipherSuite>,
+            finalization: opaque_ke::CredentialFinalization<opaque_auth::TemplateCipherSuite>,
+        ) -> Result<opaque_ke::ServerLoginFinishResult<opaque_auth::TemplateCipherSuite>, opaque_ke::errors::ProtocolError>
+        {
+            opaque_auth::login_server_finish(state, finalization)
+        }
+
+        /// Hold a `login-start` response's `ServerLogin` state until the matching `login-finish`
+        /// challenge arrives for the same `handle`. See `opaque_login_sessions`.
+        pub fn store_opaque_login_session(
+            &self,
+            handle: String,
+            state: opaque_ke::ServerLogin<opaque_auth::TemplateCipherSuite>,
+        ) {
+            let AgentVariant::Server { opaque_login_sessions, .. } = &self.variant else {
+                panic!("store_opaque_login_session called on non-server variant")
+            };
+            opaque_login_sessions.lock().unwrap().insert(handle, state);
+        }
+
+        /// Retrieve and remove the `ServerLogin` state stashed by `store_opaque_login_session`,
+        /// if a matching `login-start` was actually processed for this handle.
+        pub fn take_opaque_login_session(
+            &self,
+            handle: &str,
+        ) -> Option<opaque_ke::ServerLogin<opaque_auth::TemplateCipherSuite>> {
+            let AgentVariant::Server { opaque_login_sessions, .. } = &self.variant else {
+                return None;
+            };
+            opaque_login_sessions.lock().unwrap().remove(handle)
+        }
+
         /// Initialize the root context for server agent (must be called after node creation)
         pub fn initialize_root_context<SE: StorageEngine + Send + Sync + 'static>(
             &self,
             node: Node<SE, Self>,
         ) {
-            if let AgentVariant::Server { root_context } = &self.variant {
+            if let AgentVariant::Server { root_context, .. } = &self.variant {
                 let _ = root_context.set(Context::new(node, MyContextData::Root));
             }
         }
 
+        /// Restores the OPAQUE server setup from the persisted `ServerConfig` row, or mints and
+        /// persists one if this is the first boot. Must run after `initialize_root_context` and
+        /// before any OPAQUE registration/login is processed -- without this, every restart would
+        /// mint a fresh `opaque_setup` (see its field doc) and permanently lock out every
+        /// password-enrolled `User` and every `IdentityRecovery` record from the previous run.
+        pub async fn rehydrate_opaque_setup(&self) -> anyhow::Result<()> {
+            let AgentVariant::Server { opaque_setup, .. } = &self.variant else {
+                anyhow::bail!("rehydrate_opaque_setup called on non-server variant");
+            };
+
+            let root_context = self.get_root_context();
+            let existing = root_context.fetch::<ServerConfigView>("true").await?;
+            let setup = if let Some(row) = existing.into_iter().next() {
+                let bytes = base64::decode(row.opaque_setup()?)?;
+                ServerSetup::<opaque_auth::TemplateCipherSuite>::deserialize(&bytes)
+                    .map_err(|e| anyhow::anyhow!("Failed to deserialize OPAQUE server setup: {e}"))?
+            } else {
+                let setup = ServerSetup::<opaque_auth::TemplateCipherSuite>::new(&mut rand::rngs::OsRng);
+                let trx = root_context.begin();
+                trx.create(&ServerConfig {
+                    opaque_setup: base64::encode(setup.serialize().to_vec()),
+                    node_signing_key: String::new(),
+                })
+                .await?;
+                trx.commit().await?;
+                setup
+            };
+
+            opaque_setup
+                .set(setup)
+                .map_err(|_| anyhow::anyhow!("OPAQUE server setup already initialized"))?;
+
+            Ok(())
+        }
+
+        /// Restores this node's long-lived Ed25519 identity from the `ServerConfig` row (or
+        /// mints and persists one on first boot), so a restart doesn't invalidate every peer's
+        /// `trust_peer_node` registration of this node. Must run after `rehydrate_opaque_setup`,
+        /// which is what guarantees the `ServerConfig` row exists by the time this reads it.
+        pub async fn rehydrate_node_signing_key(&self) -> anyhow::Result<()> {
+            let AgentVariant::Server { node_signing_key, .. } = &self.variant else {
+                anyhow::bail!("rehydrate_node_signing_key called on non-server variant");
+            };
+
+            let root_context = self.get_root_context();
+            let row = root_context
+                .fetch::<ServerConfigView>("true")
+                .await?
+                .into_iter()
+                .next()
+                .ok_or_else(|| {
+                    anyhow::anyhow!(
+                        "No ServerConfig row found -- rehydrate_opaque_setup must run first"
+                    )
+                })?;
+
+            let stored = row.node_signing_key()?;
+            let signing_key = if stored.is_empty() {
+                let mut seed = [0u8; 32];
+                rand::rngs::OsRng.fill_bytes(&mut seed);
+
+                let trx = root_context.begin();
+                let row_mut = trx.edit(&row).await?;
+                row_mut.node_signing_key().set(&base64::encode(seed))?;
+                trx.commit().await?;
+
+                SigningKey::from_bytes(&seed)
+            } else {
+                let bytes = base64::decode(stored)?;
+                let seed: [u8; 32] = bytes
+                    .try_into()
+                    .map_err(|_| anyhow::anyhow!("Invalid node signing key length"))?;
+                SigningKey::from_bytes(&seed)
+            };
+
+            node_signing_key
+                .set(signing_key)
+                .map_err(|_| anyhow::anyhow!("Node signing key already initialized"))?;
+
+            Ok(())
+        }
+
+        /// Reloads the in-memory membership cache from persisted `RoomMembership` rows. Storage
+        /// is the authoritative source; the cache is strictly a synchronous-lookup mirror of it
+        /// for `filter_predicate`/`check_read`/`check_event`. Must be called once after
+        /// `initialize_root_context` and before the server starts accepting connections, or a
+        /// restart starts every cache empty and locks every user out of every room they'd
+        /// previously joined.
+        pub async fn rehydrate_memberships(&self) -> anyhow::Result<()> {
+            if !matches!(self.variant, AgentVariant::Server { .. }) {
+                anyhow::bail!("rehydrate_memberships called on non-server variant");
+            }
+
+            let root_context = self.get_root_context();
+            for membership in root_context.fetch::<RoomMembershipView>("true").await? {
+                let user_id = proto::EntityId::from_base64(&membership.user()?)?;
+                let room_id = proto::EntityId::from_base64(&membership.room()?)?;
+                self.record_membership(user_id, room_id, membership.role()?);
+            }
+
+            Ok(())
+        }
+
         /// Get root context for server operations (panics if not initialized)
         fn get_root_context(&self) -> &Context {
-            if let AgentVariant::Server { root_context } = &self.variant {
+            if let AgentVariant::Server { root_context, .. } = &self.variant {
                 root_context
                     .get()
                     .expect("Root context not initialized - call initialize_root_context first")
@@ -135,35 +1165,78 @@ mod policy_impl {
             C: Iterable<Self::ContextData>,
         {
             match &self.variant {
-                AgentVariant::Client { signing_key } => {
+                AgentVariant::Client { signing_key } | AgentVariant::ClientPassword { signing_key } => {
                     // Should only have one context data item
                     let mut auth_datas = Vec::new();
                     for ctx in cdata.iterable() {
                         match ctx {
-                            MyContextData::User(user_id) => {
+                            // Admin is signed exactly like User: admin status is something
+                            // `check_request` re-derives from the signer's verified identity
+                            // against the admin set, never something the client asserts.
+                            MyContextData::User(user_id) | MyContextData::Admin(user_id) => {
                                 let request_bytes = serde_json::to_vec(request)
                                     .ok()
                                     .expect("Failed to serialize request");
-                                let signature = signing_key.sign(&request_bytes);
 
-                                let mut auth_data = Vec::with_capacity(80);
+                                // Bind the signature to this specific moment: a 16-byte random
+                                // nonce plus an 8-byte millisecond timestamp, both signed over
+                                // along with the request body so a captured AuthData can't be
+                                // replayed outside the timestamp window (see check_request).
+                                let mut nonce_bytes = [0u8; 16];
+                                rand::thread_rng().fill_bytes(&mut nonce_bytes);
+                                let timestamp_ms = now_unix_ms();
+
+                                let mut signing_payload = request_bytes;
+                                signing_payload.extend_from_slice(&nonce_bytes);
+                                signing_payload.extend_from_slice(&timestamp_ms.to_be_bytes());
+
+                                let signature = signing_key.sign(&signing_payload);
+
+                                let mut auth_data = Vec::with_capacity(104);
                                 auth_data.extend_from_slice(&user_id.to_bytes());
                                 auth_data.extend_from_slice(&signature.to_bytes());
+                                auth_data.extend_from_slice(&nonce_bytes);
+                                auth_data.extend_from_slice(&timestamp_ms.to_be_bytes());
 
                                 auth_datas.push(proto::AuthData(auth_data));
                             }
-                            MyContextData::Anonymous => {
-                                // Anonymous context sends empty auth (for user self-registration)
-                                auth_datas.push(proto::AuthData(vec![]));
+                            MyContextData::Anonymous { invite_code } => {
+                                // Anonymous auth carries the invite code (if any), tagged so
+                                // check_request can tell it apart from an Ed25519/token payload.
+                                auth_datas.push(proto::AuthData(anonymous_auth_data(&invite_code)));
                             }
                             MyContextData::Root => {
                                 // Root should never be used from client
                                 panic!("Root context should not be used from client");
                             }
+                            MyContextData::Scoped { .. } => {
+                                // A Client/ClientPassword key signs as a full identity; it has no
+                                // scoped token to present. ScopedToken is the variant for this.
+                                panic!("Scoped context requires a ScopedToken agent, not a signing key");
+                            }
                         }
                     }
                     auth_datas
                 }
+                AgentVariant::Token { token, .. } => {
+                    // The token itself is the proof of authentication; no per-request signing.
+                    cdata
+                        .iterable()
+                        .map(|ctx| match ctx {
+                            MyContextData::Anonymous { invite_code } => {
+                                proto::AuthData(anonymous_auth_data(&invite_code))
+                            }
+                            _ => proto::AuthData(token.clone()),
+                        })
+                        .collect()
+                }
+                AgentVariant::ScopedToken { token } => {
+                    // Same story as Token: the scoped token bytes are the whole credential.
+                    cdata
+                        .iterable()
+                        .map(|_| proto::AuthData(token.clone()))
+                        .collect()
+                }
                 AgentVariant::Server { .. } => vec![proto::AuthData(vec![])],
             }
         }
@@ -185,21 +1258,43 @@ mod policy_impl {
             for auth_data in auth_datas {
                 let bytes = &auth_data.0;
 
-                // Empty auth data means Anonymous context (used for user self-registration)
-                if bytes.is_empty() {
-                    info!("Empty auth data - allowing as Anonymous context");
-                    contexts.push(MyContextData::Anonymous);
+                // A bearer token needs only a constant-time HMAC check, no storage lookup.
+                if bytes.starts_with(TOKEN_MAGIC) {
+                    let user_id = self.verify_token(bytes)?;
+                    contexts.push(self.context_for_verified_user(user_id));
+                    continue;
+                }
+
+                // A scoped token likewise needs only an HMAC check, but yields a Scoped context
+                // rather than re-deriving full User/Admin status.
+                if bytes.starts_with(SCOPED_TOKEN_MAGIC) {
+                    let (user_id, scopes, expires_at) = self.verify_scoped_token(bytes)?;
+                    contexts.push(MyContextData::Scoped { user_id, scopes, expires_at });
+                    continue;
+                }
+
+                // Anonymous context (used for user self-registration), optionally carrying an
+                // invite code that check_event will redeem before allowing a User to be created.
+                if bytes.starts_with(ANONYMOUS_MAGIC) || bytes.is_empty() {
+                    let code_bytes = bytes.strip_prefix(ANONYMOUS_MAGIC.as_slice()).unwrap_or(&[]);
+                    let invite_code = if code_bytes.is_empty() {
+                        None
+                    } else {
+                        Some(String::from_utf8_lossy(code_bytes).into_owned())
+                    };
+                    info!("Allowing as Anonymous context (invite present: {})", invite_code.is_some());
+                    contexts.push(MyContextData::Anonymous { invite_code });
                     continue;
                 }
 
-                if bytes.len() < 80 {
+                if bytes.len() < 104 {
                     info!(
-                        "Insufficient auth data: got {} bytes, expected 80. Request: {:?}",
+                        "Insufficient auth data: got {} bytes, expected 104. Request: {:?}",
                         bytes.len(),
                         request.body
                     );
                     return Err(ValidationError::ValidationFailed(format!(
-                        "Insufficient auth data: got {} bytes, expected 80",
+                        "Insufficient auth data: got {} bytes, expected 104",
                         bytes.len()
                     )));
                 }
@@ -215,18 +1310,55 @@ mod policy_impl {
                 })?;
                 let signature = Signature::from_bytes(&signature_bytes);
 
-                // Fetch user and validate signature
-                let user_view = match &self.variant {
-                    AgentVariant::Server { .. } => {
+                let nonce_bytes: [u8; 16] = bytes[80..96].try_into().map_err(|_| {
+                    ValidationError::ValidationFailed("Invalid nonce".to_string())
+                })?;
+                let nonce = u128::from_be_bytes(nonce_bytes);
+
+                let timestamp_bytes: [u8; 8] = bytes[96..104].try_into().map_err(|_| {
+                    ValidationError::ValidationFailed("Invalid timestamp".to_string())
+                })?;
+                let timestamp_ms = i64::from_be_bytes(timestamp_bytes);
+
+                let now_ms = now_unix_ms();
+                if (now_ms - timestamp_ms).abs() > REQUEST_TIMESTAMP_WINDOW_MS {
+                    return Err(ValidationError::ValidationFailed(format!(
+                        "Request timestamp {} outside of the {}ms freshness window (now {})",
+                        timestamp_ms, REQUEST_TIMESTAMP_WINDOW_MS, now_ms
+                    )));
+                }
+
+                // Fetch user and check the nonce hasn't been replayed. The nonce isn't recorded
+                // here -- only checked -- because `user_id` is still unverified at this point; an
+                // unauthenticated caller could otherwise grow `seen_nonces` forever by sending a
+                // flood of random 16-byte `user_id`s, a memory-exhaustion DoS against the exact
+                // mechanism meant to harden auth. It's recorded below, once the signature has
+                // actually been verified against this user's real `pub_key`.
+                let (seen_nonces, user_view) = match &self.variant {
+                    AgentVariant::Server { seen_nonces, .. } => {
+                        {
+                            let seen_nonces = seen_nonces.lock().unwrap();
+                            if let Some(entries) = seen_nonces.get(&user_id) {
+                                if entries.iter().any(|(_, n)| *n == nonce) {
+                                    return Err(ValidationError::ValidationFailed(
+                                        "Replayed request nonce".to_string(),
+                                    ));
+                                }
+                            }
+                        }
+
                         let root_context = self.get_root_context();
                         info!("Fetching user {} with root context", user_id.to_base64());
                         let view = root_context.get::<UserView>(user_id).await.map_err(|e| {
                             ValidationError::ValidationFailed(format!("User not found: {}", e))
                         })?;
                         info!("Successfully fetched user {}", user_id.to_base64());
-                        view
+                        (seen_nonces.clone(), view)
                     }
-                    AgentVariant::Client { .. } => {
+                    AgentVariant::Client { .. }
+                    | AgentVariant::ClientPassword { .. }
+                    | AgentVariant::Token { .. }
+                    | AgentVariant::ScopedToken { .. } => {
                         return Err(ValidationError::ValidationFailed(
                             "Client cannot validate requests".to_string(),
                         ));
@@ -262,12 +1394,14 @@ mod policy_impl {
                         ValidationError::ValidationFailed(format!("Invalid public key: {}", e))
                     })?;
 
-                let request_bytes = serde_json::to_vec(request).map_err(|e| {
+                let mut signing_payload = serde_json::to_vec(request).map_err(|e| {
                     ValidationError::ValidationFailed(format!("Failed to serialize request: {}", e))
                 })?;
+                signing_payload.extend_from_slice(&nonce_bytes);
+                signing_payload.extend_from_slice(&timestamp_bytes);
 
                 verifying_key
-                    .verify(&request_bytes, &signature)
+                    .verify(&signing_payload, &signature)
                     .map_err(|e| {
                         ValidationError::ValidationFailed(format!(
                             "Signature verification failed: {}",
@@ -275,7 +1409,24 @@ mod policy_impl {
                         ))
                     })?;
 
-                contexts.push(MyContextData::User(user_id));
+                // Only now, with the signature verified against this user's real `pub_key`, is it
+                // safe to record the nonce -- doing it earlier would let an unauthenticated caller
+                // grow this map without bound (see the comment above).
+                {
+                    let mut seen_nonces = seen_nonces.lock().unwrap();
+                    let entries = seen_nonces.entry(user_id).or_default();
+                    entries.retain(|(ts, _)| now_ms - ts <= REQUEST_TIMESTAMP_WINDOW_MS);
+                    entries.push((timestamp_ms, nonce));
+
+                    if seen_nonces.len() > MAX_SEEN_NONCE_USERS {
+                        seen_nonces.retain(|_, entries| {
+                            entries.retain(|(ts, _)| now_ms - ts <= REQUEST_TIMESTAMP_WINDOW_MS);
+                            !entries.is_empty()
+                        });
+                    }
+                }
+
+                contexts.push(self.context_for_verified_user(user_id));
             }
 
             Ok(contexts)
@@ -285,7 +1436,7 @@ mod policy_impl {
             &self,
             _node: &Node<SE, Self>,
             cdata: &Self::ContextData,
-            _entity_before: &Entity,
+            entity_before: &Entity,
             entity_after: &Entity,
             _event: &proto::Event,
         ) -> Result<Option<proto::Attestation>, AccessDenied> {
@@ -301,13 +1452,168 @@ mod policy_impl {
                 return Ok(None);
             }
 
-            // Anonymous context can only create User entities (self-registration)
-            if matches!(cdata, MyContextData::Anonymous) {
+            // Scoped context: capability-gated access on behalf of `user_id`, restricted to the
+            // granted `Scopes` rather than full account privileges. Each collection below maps to
+            // the one capability that can write it; anything else is denied outright.
+            if let MyContextData::Scoped { user_id, scopes, expires_at } = cdata {
+                if now_unix_ms() / 1000 > *expires_at {
+                    return Err(AccessDenied::ByPolicy("Scoped token has expired"));
+                }
+
+                let collection_str = entity_after.collection().as_str();
+
+                if collection_str.eq_ignore_ascii_case("room") {
+                    if !scopes.contains(Scopes::CREATE_ROOM) {
+                        return Err(AccessDenied::ByPolicy("Scoped token lacks CreateRoom"));
+                    }
+                    return Ok(None);
+                }
+
+                if collection_str.eq_ignore_ascii_case("message") {
+                    if !scopes.contains(Scopes::WRITE_MESSAGE) {
+                        return Err(AccessDenied::ByPolicy("Scoped token lacks WriteMessage"));
+                    }
+                    if let Some(Value::String(message_user)) = entity_after.value("user") {
+                        let message_user_id = proto::EntityId::from_base64(&message_user)
+                            .map_err(|_| AccessDenied::ByPolicy("Invalid user ID in message"))?;
+                        if &message_user_id != user_id {
+                            return Err(AccessDenied::ByPolicy(
+                                "Scoped token cannot post as another user",
+                            ));
+                        }
+                    }
+                    if let Some(Value::String(message_room)) = entity_after.value("room") {
+                        let room_id = proto::EntityId::from_base64(&message_room)
+                            .map_err(|_| AccessDenied::ByPolicy("Invalid room ID in message"))?;
+                        if self.member_role(user_id, &room_id).is_none() {
+                            return Err(AccessDenied::ByPolicy(
+                                "Cannot post into a room you haven't joined",
+                            ));
+                        }
+                    }
+                    return Ok(None);
+                }
+
+                if collection_str.eq_ignore_ascii_case("user")
+                    || collection_str.eq_ignore_ascii_case("roommembership")
+                    || collection_str.eq_ignore_ascii_case("invite")
+                {
+                    if !scopes.contains(Scopes::MANAGE_USERS) {
+                        return Err(AccessDenied::ByPolicy("Scoped token lacks ManageUsers"));
+                    }
+                    if collection_str.eq_ignore_ascii_case("roommembership") {
+                        if let (
+                            Some(Value::String(user)),
+                            Some(Value::String(room)),
+                            Some(Value::String(role)),
+                        ) = (
+                            entity_after.value("user"),
+                            entity_after.value("room"),
+                            entity_after.value("role"),
+                        ) {
+                            let uid = proto::EntityId::from_base64(&user)
+                                .map_err(|_| AccessDenied::ByPolicy("Invalid user ID in membership"))?;
+                            let rid = proto::EntityId::from_base64(&room)
+                                .map_err(|_| AccessDenied::ByPolicy("Invalid room ID in membership"))?;
+                            self.record_membership(uid, rid, role);
+                        }
+                    }
+                    return Ok(None);
+                }
+
+                return Err(AccessDenied::ByPolicy("Scoped token cannot write to this collection"));
+            }
+
+            // An OPAQUE challenge (registration or login recovery) may be opened by anyone,
+            // authenticated or not -- that's the whole point, it's how an unauthenticated new
+            // device talks to the server during `recover_identity`. Only the server's own
+            // background responder (which runs under Root, already handled above) may fill in
+            // `response`; every other context reaching this point is denied that specific change.
+            if entity_after
+                .collection()
+                .as_str()
+                .eq_ignore_ascii_case("opaquechallenge")
+            {
+                let response_changed = entity_before.value("response") != entity_after.value("response");
+                if response_changed {
+                    return Err(AccessDenied::ByPolicy(
+                        "Only the server may answer an OPAQUE challenge",
+                    ));
+                }
+                return Ok(None);
+            }
+
+            // A token issuance request may be opened by any authenticated user, but only for
+            // themselves; only the server's own background responder (handled under Root above)
+            // may fill in `token`, mirroring the OpaqueChallenge check just above.
+            if entity_after
+                .collection()
+                .as_str()
+                .eq_ignore_ascii_case("tokenissuance")
+            {
+                let token_changed = entity_before.value("token") != entity_after.value("token");
+                if token_changed {
+                    return Err(AccessDenied::ByPolicy(
+                        "Only the server may answer a token issuance request",
+                    ));
+                }
+                let (MyContextData::User(authenticated_user) | MyContextData::Admin(authenticated_user)) = cdata
+                else {
+                    return Err(AccessDenied::ByPolicy(
+                        "Only an authenticated user may request a token",
+                    ));
+                };
+                if let Some(Value::String(requested_user)) = entity_after.value("user") {
+                    let requested_user_id = proto::EntityId::from_base64(&requested_user)
+                        .map_err(|_| AccessDenied::ByPolicy("Invalid user ID in token request"))?;
+                    if &requested_user_id != authenticated_user {
+                        return Err(AccessDenied::ByPolicy(
+                            "Cannot request a token for another user",
+                        ));
+                    }
+                }
+                return Ok(None);
+            }
+
+            // Only the owning user may register or update their own recovery record.
+            if entity_after
+                .collection()
+                .as_str()
+                .eq_ignore_ascii_case("identityrecovery")
+            {
+                let (MyContextData::User(authenticated_user) | MyContextData::Admin(authenticated_user)) = cdata
+                else {
+                    return Err(AccessDenied::ByPolicy(
+                        "Only an authenticated user may register identity recovery",
+                    ));
+                };
+                if let Some(Value::String(owner)) = entity_after.value("user") {
+                    let owner_id = proto::EntityId::from_base64(&owner)
+                        .map_err(|_| AccessDenied::ByPolicy("Invalid user ID in recovery record"))?;
+                    if &owner_id != authenticated_user {
+                        return Err(AccessDenied::ByPolicy(
+                            "Cannot write another user's recovery record",
+                        ));
+                    }
+                }
+                return Ok(None);
+            }
+
+            // Anonymous context can only create User entities (self-registration), and only with
+            // a redeemable invite
+            if let MyContextData::Anonymous { invite_code } = cdata {
                 if entity_after
                     .collection()
                     .as_str()
                     .eq_ignore_ascii_case("user")
                 {
+                    let code = invite_code
+                        .as_deref()
+                        .ok_or(AccessDenied::ByPolicy("invite required"))?;
+                    let role = self.redeem_invite(code, entity_after.id())?;
+                    if role == "admin" {
+                        self.grant_admin(entity_after.id());
+                    }
                     info!("Allowing User entity operation for Anonymous context");
                     return Ok(None);
                 } else {
@@ -321,19 +1627,132 @@ mod policy_impl {
                 }
             }
 
-            // Authenticated users: validate Message ownership
+            // Admin context may additionally manage the invite ledger directly (e.g. revoking an
+            // outstanding invite by writing to it), on top of every permission an authenticated
+            // User has (see the merged `User | Admin` match arms below).
+            if let MyContextData::Admin(_) = cdata {
+                if entity_after
+                    .collection()
+                    .as_str()
+                    .eq_ignore_ascii_case("invite")
+                {
+                    if let Some(Value::String(code_hash)) = entity_after.value("code_hash") {
+                        self.revoke_invite_record(&code_hash);
+                    }
+                    return Ok(None);
+                }
+            }
+
+            // RoomMembership writes keep the in-memory ACL cache that filter_predicate/check_read
+            // consult current. Granting or altering someone *else's* membership requires already
+            // being an owner/moderator of that room (Admin bypasses this check, though not the
+            // Message checks below); self-joining is restricted to `role = "member"`, except a room with
+            // no members at all yet may have its first membership self-granted as `owner` so a
+            // freshly created room ends up with someone able to manage it.
+            if entity_after
+                .collection()
+                .as_str()
+                .eq_ignore_ascii_case("roommembership")
+            {
+                if let (Some(Value::String(user)), Some(Value::String(room)), Some(Value::String(role))) = (
+                    entity_after.value("user"),
+                    entity_after.value("room"),
+                    entity_after.value("role"),
+                ) {
+                    let user_id = proto::EntityId::from_base64(&user)
+                        .map_err(|_| AccessDenied::ByPolicy("Invalid user ID in membership"))?;
+                    let room_id = proto::EntityId::from_base64(&room)
+                        .map_err(|_| AccessDenied::ByPolicy("Invalid room ID in membership"))?;
+
+                    // The Message-deletion check below keys off `RoomMembership.role`, so it must
+                    // only ever be one of the three values that type is documented to hold.
+                    if !matches!(role.as_str(), "member" | "moderator" | "owner") {
+                        return Err(AccessDenied::ByPolicy("Invalid role for room membership"));
+                    }
+
+                    if !matches!(cdata, MyContextData::Admin(_)) {
+                        let MyContextData::User(authenticated_user) = cdata else {
+                            return Err(AccessDenied::ByPolicy(
+                                "Only an authenticated user can manage room membership",
+                            ));
+                        };
+
+                        if user_id == *authenticated_user {
+                            let room_is_new = !self.room_has_any_member(&room_id);
+                            if role != "member" && !(role == "owner" && room_is_new) {
+                                return Err(AccessDenied::ByPolicy(
+                                    "Can only self-join a room as a member",
+                                ));
+                            }
+                        } else {
+                            match self.member_role(authenticated_user, &room_id).as_deref() {
+                                Some("owner") | Some("moderator") => {}
+                                _ => {
+                                    return Err(AccessDenied::ByPolicy(
+                                        "Only a room owner or moderator can grant membership to another user",
+                                    ))
+                                }
+                            }
+                        }
+                    }
+
+                    self.record_membership(user_id, room_id, role);
+                }
+            }
+
+            // Authenticated users: validate Message ownership and room membership
             if entity_after
                 .collection()
                 .as_str()
                 .eq_ignore_ascii_case("message")
             {
-                if let MyContextData::User(authenticated_user) = cdata {
+                if let MyContextData::User(authenticated_user) | MyContextData::Admin(authenticated_user) =
+                    cdata
+                {
                     if let Some(Value::String(message_user)) = entity_after.value("user") {
                         let message_user_id = proto::EntityId::from_base64(&message_user)
                             .map_err(|_| AccessDenied::ByPolicy("Invalid user ID in message"))?;
 
                         if &message_user_id != authenticated_user {
-                            return Err(AccessDenied::ByPolicy("Message user mismatch"));
+                            // Not the author. The only write we allow here is a moderator/owner
+                            // toggling `deleted` to true; anything that also touches the message
+                            // content is a forgery attempt and is denied outright.
+                            let is_delete_only = entity_before.value("text") == entity_after.value("text")
+                                && entity_before.value("user") == entity_after.value("user")
+                                && entity_before.value("room") == entity_after.value("room")
+                                && matches!(entity_after.value("deleted"), Some(Value::Bool(true)));
+
+                            if !is_delete_only {
+                                return Err(AccessDenied::ByPolicy(
+                                    "Only the message author can edit its content",
+                                ));
+                            }
+
+                            let room_id = match entity_after.value("room") {
+                                Some(Value::String(room)) => proto::EntityId::from_base64(&room)
+                                    .map_err(|_| AccessDenied::ByPolicy("Invalid room ID in message"))?,
+                                _ => return Err(AccessDenied::ByPolicy("Message missing room")),
+                            };
+
+                            match self.member_role(authenticated_user, &room_id).as_deref() {
+                                Some("owner") | Some("moderator") => {}
+                                _ => {
+                                    return Err(AccessDenied::ByPolicy(
+                                        "Only a room owner or moderator can delete others' messages",
+                                    ))
+                                }
+                            }
+                        }
+                    }
+
+                    if let Some(Value::String(message_room)) = entity_after.value("room") {
+                        let room_id = proto::EntityId::from_base64(&message_room)
+                            .map_err(|_| AccessDenied::ByPolicy("Invalid room ID in message"))?;
+
+                        if self.member_role(authenticated_user, &room_id).is_none() {
+                            return Err(AccessDenied::ByPolicy(
+                                "Cannot post into a room you haven't joined",
+                            ));
                         }
                     }
                 }
@@ -345,27 +1764,42 @@ mod policy_impl {
         fn validate_received_event<SE: StorageEngine>(
             &self,
             _node: &Node<SE, Self>,
-            _received_from_node: &proto::EntityId,
-            _event: &Attested<proto::Event>,
+            received_from_node: &proto::EntityId,
+            event: &Attested<proto::Event>,
         ) -> Result<(), AccessDenied> {
-            Ok(())
+            let digest_input = serde_json::to_vec(&event.payload)
+                .map_err(|_| AccessDenied::ByPolicy("Malformed event"))?;
+            self.verify_peer_attestation(received_from_node, &event.attestation, &digest_input)
         }
 
         fn attest_state<SE: StorageEngine>(
             &self,
             _node: &Node<SE, Self>,
-            _state: &proto::EntityState,
+            state: &proto::EntityState,
         ) -> Option<proto::Attestation> {
-            None
+            if !matches!(self.variant, AgentVariant::Server { .. }) {
+                return None;
+            }
+            let node_signing_key = self.get_node_signing_key();
+
+            let digest = Sha256::digest(serde_json::to_vec(state).ok()?);
+            let signature = node_signing_key.sign(&digest);
+
+            let mut bytes = Vec::with_capacity(32 + 64);
+            bytes.extend_from_slice(node_signing_key.verifying_key().as_bytes());
+            bytes.extend_from_slice(&signature.to_bytes());
+            Some(proto::Attestation(bytes))
         }
 
         fn validate_received_state<SE: StorageEngine>(
             &self,
             _node: &Node<SE, Self>,
-            _received_from_node: &proto::EntityId,
-            _state: &Attested<proto::EntityState>,
+            received_from_node: &proto::EntityId,
+            state: &Attested<proto::EntityState>,
         ) -> Result<(), AccessDenied> {
-            Ok(())
+            let digest_input = serde_json::to_vec(&state.payload)
+                .map_err(|_| AccessDenied::ByPolicy("Malformed state"))?;
+            self.verify_peer_attestation(received_from_node, &state.attestation, &digest_input)
         }
 
         fn can_access_collection<C>(
@@ -381,14 +1815,69 @@ mod policy_impl {
 
         fn check_read<C>(
             &self,
-            _data: &C,
-            _id: &proto::EntityId,
-            _collection: &proto::CollectionId,
-            _state: &proto::State,
+            data: &C,
+            id: &proto::EntityId,
+            collection: &proto::CollectionId,
+            state: &proto::State,
         ) -> Result<(), AccessDenied>
         where
             C: Iterable<Self::ContextData>,
         {
+            let collection_str = collection.as_str();
+            let is_room = collection_str.eq_ignore_ascii_case("room");
+            let is_message = collection_str.eq_ignore_ascii_case("message");
+            if !is_room && !is_message {
+                return Ok(());
+            }
+
+            for cdata in data.iterable() {
+                match cdata {
+                    MyContextData::Root => return Ok(()),
+                    MyContextData::Anonymous { .. } => {
+                        return Err(AccessDenied::ByPolicy(
+                            "Anonymous cannot read rooms or messages",
+                        ))
+                    }
+                    MyContextData::User(user_id) | MyContextData::Admin(user_id) => {
+                        let room_id = if is_room {
+                            *id
+                        } else {
+                            match state.value("room") {
+                                Some(Value::String(room)) => proto::EntityId::from_base64(&room)
+                                    .map_err(|_| AccessDenied::ByPolicy("Invalid room ID in message"))?,
+                                _ => return Err(AccessDenied::ByPolicy("Message missing room")),
+                            }
+                        };
+
+                        if self.member_role(&user_id, &room_id).is_none() {
+                            return Err(AccessDenied::ByPolicy("Not a member of this room"));
+                        }
+                    }
+                    MyContextData::Scoped { user_id, scopes, expires_at } => {
+                        if now_unix_ms() / 1000 > expires_at {
+                            return Err(AccessDenied::ByPolicy("Scoped token has expired"));
+                        }
+                        if !scopes.contains(Scopes::READ_ROOM) {
+                            return Err(AccessDenied::ByPolicy("Scoped token lacks ReadRoom"));
+                        }
+
+                        let room_id = if is_room {
+                            *id
+                        } else {
+                            match state.value("room") {
+                                Some(Value::String(room)) => proto::EntityId::from_base64(&room)
+                                    .map_err(|_| AccessDenied::ByPolicy("Invalid room ID in message"))?,
+                                _ => return Err(AccessDenied::ByPolicy("Message missing room")),
+                            }
+                        };
+
+                        if self.member_role(&user_id, &room_id).is_none() {
+                            return Err(AccessDenied::ByPolicy("Not a member of this room"));
+                        }
+                    }
+                }
+            }
+
             Ok(())
         }
 
@@ -423,16 +1912,65 @@ mod policy_impl {
 
         fn filter_predicate<C>(
             &self,
-            _data: &C,
-            _collection: &proto::CollectionId,
+            data: &C,
+            collection: &proto::CollectionId,
             predicate: ankurah::ankql::ast::Predicate,
         ) -> Result<ankurah::ankql::ast::Predicate, AccessDenied>
         where
             C: Iterable<Self::ContextData>,
         {
+            use ankurah::ankql::ast::Predicate;
+
+            let collection_str = collection.as_str();
+            let is_room = collection_str.eq_ignore_ascii_case("room");
+            let is_message = collection_str.eq_ignore_ascii_case("message");
+            if !is_room && !is_message {
+                return Ok(predicate);
+            }
+
+            for cdata in data.iterable() {
+                match cdata {
+                    MyContextData::Root => return Ok(predicate),
+                    MyContextData::Anonymous { .. } => {
+                        return Err(AccessDenied::ByPolicy(
+                            "Anonymous cannot browse rooms or messages",
+                        ))
+                    }
+                    MyContextData::User(user_id) | MyContextData::Admin(user_id) => {
+                        let allowed_rooms = self.member_rooms(&user_id);
+                        let id_field = if is_room { "id" } else { "room" };
+                        let membership_predicate = Self::room_membership_predicate(id_field, &allowed_rooms);
+
+                        return Ok(Predicate::And(
+                            Box::new(predicate),
+                            Box::new(membership_predicate),
+                        ));
+                    }
+                    MyContextData::Scoped { user_id, scopes, expires_at } => {
+                        if now_unix_ms() / 1000 > expires_at {
+                            return Err(AccessDenied::ByPolicy("Scoped token has expired"));
+                        }
+                        if !scopes.contains(Scopes::READ_ROOM) {
+                            return Err(AccessDenied::ByPolicy(
+                                "Scoped token lacks ReadRoom",
+                            ));
+                        }
+
+                        let allowed_rooms = self.member_rooms(&user_id);
+                        let id_field = if is_room { "id" } else { "room" };
+                        let membership_predicate = Self::room_membership_predicate(id_field, &allowed_rooms);
+
+                        return Ok(Predicate::And(
+                            Box::new(predicate),
+                            Box::new(membership_predicate),
+                        ));
+                    }
+                }
+            }
+
             Ok(predicate)
         }
     }
 }
 
-pub use policy_impl::{MyContextData, UserKeyPairAgent};
+pub use policy_impl::{decode_scoped_token_claims, MyContextData, Scopes, UserKeyPairAgent};
@@ -0,0 +1,122 @@
+//! OPAQUE (aPAKE) password registration and login.
+//!
+//! The server only ever stores what `opaque-ke` calls the registration envelope; it never
+//! observes the password itself or anything equivalent to it. A successful login yields an
+//! `export_key`, which `derive_signing_key` turns into the same Ed25519 `SigningKey` the
+//! existing Ed25519 client variant already knows how to sign with, so the request
+//! signing/verification path (`sign_request`/`check_request`) needs no changes to support it.
+
+use ed25519_dalek::SigningKey;
+use opaque_ke::{
+    errors::ProtocolError, CipherSuite, ClientLogin, ClientLoginFinishParameters,
+    ClientLoginFinishResult, ClientRegistration, ClientRegistrationFinishParameters,
+    ClientRegistrationFinishResult, CredentialFinalization, CredentialRequest, CredentialResponse,
+    RegistrationRequest, RegistrationResponse, RegistrationUpload, ServerLogin,
+    ServerLoginFinishResult, ServerLoginStartParameters, ServerLoginStartResult,
+    ServerRegistration, ServerSetup,
+};
+use rand::rngs::OsRng;
+use sha2::{Digest, Sha256};
+
+/// Cipher suite used for every OPAQUE exchange in this template: ristretto255 for the OPRF and
+/// key exchange groups, and a 3DH key exchange, per the `opaque-ke` quickstart.
+pub struct TemplateCipherSuite;
+
+impl CipherSuite for TemplateCipherSuite {
+    type OprfCs = opaque_ke::Ristretto255;
+    type KeGroup = opaque_ke::Ristretto255;
+    type KeyExchange = opaque_ke::key_exchange::tripledh::TripleDh<Self::KeGroup, Self::Hash>;
+    type Hash = sha2::Sha512;
+    type Ksf = argon2::Argon2<'static>;
+}
+
+/// Registration: the client blinds its password into a `RegistrationRequest`.
+pub fn register_start(
+    password: &[u8],
+) -> Result<(ClientRegistration<TemplateCipherSuite>, RegistrationRequest<TemplateCipherSuite>), ProtocolError>
+{
+    let result = ClientRegistration::<TemplateCipherSuite>::start(&mut OsRng, password)?;
+    Ok((result.state, result.message))
+}
+
+/// Registration: the server evaluates the OPRF over the client's blinded password.
+pub fn register_server_start(
+    setup: &ServerSetup<TemplateCipherSuite>,
+    request: RegistrationRequest<TemplateCipherSuite>,
+    credential_identifier: &[u8],
+) -> Result<RegistrationResponse<TemplateCipherSuite>, ProtocolError> {
+    Ok(ServerRegistration::<TemplateCipherSuite>::start(setup, request, credential_identifier)?.message)
+}
+
+/// Registration: the client derives `export_key` and the envelope to upload and store.
+pub fn register_finish(
+    state: ClientRegistration<TemplateCipherSuite>,
+    password: &[u8],
+    response: RegistrationResponse<TemplateCipherSuite>,
+) -> Result<ClientRegistrationFinishResult<TemplateCipherSuite>, ProtocolError> {
+    state.finish(
+        &mut OsRng,
+        password,
+        response,
+        ClientRegistrationFinishParameters::default(),
+    )
+}
+
+/// Registration: the server turns the client's upload into the registration record to store
+/// (the caller persists it, e.g. as `User.password_envelope`).
+pub fn register_server_finish(
+    upload: RegistrationUpload<TemplateCipherSuite>,
+) -> Result<ServerRegistration<TemplateCipherSuite>, ProtocolError> {
+    ServerRegistration::<TemplateCipherSuite>::finish(upload)
+}
+
+/// Login: the client starts a `ClientLogin` against its password.
+pub fn login_start(
+    password: &[u8],
+) -> Result<(ClientLogin<TemplateCipherSuite>, CredentialRequest<TemplateCipherSuite>), ProtocolError>
+{
+    let result = ClientLogin::<TemplateCipherSuite>::start(&mut OsRng, password)?;
+    Ok((result.state, result.message))
+}
+
+/// Login: the server responds using the stored registration envelope for this user.
+pub fn login_server_start(
+    setup: &ServerSetup<TemplateCipherSuite>,
+    password_file: Option<ServerRegistration<TemplateCipherSuite>>,
+    credential_identifier: &[u8],
+    request: CredentialRequest<TemplateCipherSuite>,
+) -> Result<ServerLoginStartResult<TemplateCipherSuite>, ProtocolError> {
+    ServerLogin::<TemplateCipherSuite>::start(
+        &mut OsRng,
+        setup,
+        password_file,
+        request,
+        credential_identifier,
+        ServerLoginStartParameters::default(),
+    )
+}
+
+/// Login: the client finishes the exchange, producing `export_key` and the finalization to send back.
+pub fn login_finish(
+    state: ClientLogin<TemplateCipherSuite>,
+    password: &[u8],
+    response: CredentialResponse<TemplateCipherSuite>,
+) -> Result<ClientLoginFinishResult<TemplateCipherSuite>, ProtocolError> {
+    state.finish(password, response, ClientLoginFinishParameters::default())
+}
+
+/// Login: the server verifies the finalization and, on success, shares the same session key.
+pub fn login_server_finish(
+    state: ServerLogin<TemplateCipherSuite>,
+    finalization: CredentialFinalization<TemplateCipherSuite>,
+) -> Result<ServerLoginFinishResult<TemplateCipherSuite>, ProtocolError> {
+    state.finish(finalization)
+}
+
+/// Deterministically derives the Ed25519 signing key seed this user authenticates with from an
+/// OPAQUE `export_key`, so a password login reconstructs the exact identity the Ed25519 client
+/// variant already knows how to prove possession of, instead of introducing a second credential.
+pub fn derive_signing_key(export_key: &[u8]) -> SigningKey {
+    let seed: [u8; 32] = Sha256::digest(export_key).into();
+    SigningKey::from_bytes(&seed)
+}
@@ -0,0 +1,212 @@
+//! Room-to-node sharding for running several Sled-backed servers as one cluster.
+//!
+//! `WebsocketServer::run` (in `ankurah_websocket_server`) is a single external entry point that
+//! doesn't yet expose a per-request routing hook, so this module can't intercept an arbitrary
+//! inbound subscription/mutation the instant it arrives and route only that request elsewhere.
+//! What `ForwardingClient` does instead is the part that's actually reachable from here: once told
+//! to forward a given room, it opens a real `ankurah_websocket_client` connection from this node to
+//! the room's owner and lets the two nodes' `Node::system` sync protocol do the rest -- the same
+//! mechanism a browser client already uses to sync with this node, just pointed at a peer node
+//! instead. After that connection is up, a local subscribe/mutate against that room converges with
+//! the owner (and the owner's changes replicate back) without this module hand-relaying individual
+//! messages.
+//!
+//! Deciding *which* rooms to forward can't happen per-request without that missing hook, so
+//! `server::main` drives it two other ways instead: "General" is connected eagerly at boot (it's
+//! the one well-known room every node can assume exists), and
+//! `ensure_forwarding_for_all_rooms_loop` periodically sweeps every `Room` this node knows about
+//! and forwards any it doesn't own -- covering rooms created later via a `CreateRoom`-scoped token
+//! too, not just "General". The remaining gap is latency, not scope: a newly-created room takes up
+//! to one sweep interval to start forwarding, rather than being routed on its very first request.
+//!
+//! Clients are unaffected either way: they keep talking to whichever node they're connected to.
+
+use ankurah::proto::EntityId;
+use ankurah::{core::storage::StorageEngine, Node};
+use ankurah_template_model::UserKeyPairAgent;
+use ankurah_websocket_client::WebsocketClient;
+use std::collections::hash_map::DefaultHasher;
+use std::collections::{HashMap, HashSet};
+use std::hash::{Hash, Hasher};
+use std::sync::{Arc, Mutex};
+
+/// One server process in the cluster.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct NodeInfo {
+    pub id: String,
+    /// Websocket URL other nodes forward to, e.g. `ws://10.0.0.2:9797`.
+    pub addr: String,
+}
+
+/// Static cluster config: which nodes exist and which one owns a given room. Consistent hashing
+/// (rather than a fixed modulus) means adding or removing a node only reshuffles the rooms whose
+/// hash falls in the changed part of the ring, instead of remapping everything.
+#[derive(Clone, Debug)]
+pub struct ClusterMetadata {
+    /// Sorted `(hash, node index)` ring. Sorted once at construction so lookups are a binary search.
+    ring: Vec<(u64, usize)>,
+    nodes: Vec<NodeInfo>,
+    local_node_id: String,
+}
+
+/// How many points each node gets on the hash ring. Higher spreads ownership more evenly across
+/// nodes at the cost of a larger ring to search.
+const VIRTUAL_NODES_PER_NODE: usize = 64;
+
+impl ClusterMetadata {
+    pub fn new(nodes: Vec<NodeInfo>, local_node_id: String) -> Self {
+        let mut ring = Vec::with_capacity(nodes.len() * VIRTUAL_NODES_PER_NODE);
+        for (index, node) in nodes.iter().enumerate() {
+            for replica in 0..VIRTUAL_NODES_PER_NODE {
+                ring.push((hash_key(&(&node.id, replica)), index));
+            }
+        }
+        ring.sort_unstable_by_key(|(hash, _)| *hash);
+
+        Self { ring, nodes, local_node_id }
+    }
+
+    /// Single-node deployment: everything is local, no forwarding ever happens.
+    pub fn single_node(local_node_id: String) -> Self {
+        Self::new(vec![NodeInfo { id: local_node_id.clone(), addr: String::new() }], local_node_id)
+    }
+
+    /// The node that owns `room_name`: the first ring entry at or after its hash, wrapping around.
+    pub fn owner_of_room(&self, room_name: &str) -> &NodeInfo {
+        self.owner_of(hash_key(&room_name))
+    }
+
+    /// The node that owns `entity_id`, for sharding non-room entities by the same ring.
+    pub fn owner_of_entity(&self, entity_id: &EntityId) -> &NodeInfo {
+        self.owner_of(hash_key(&entity_id.to_string()))
+    }
+
+    fn owner_of(&self, hash: u64) -> &NodeInfo {
+        let index = match self.ring.binary_search_by_key(&hash, |(h, _)| *h) {
+            Ok(i) | Err(i) => i % self.ring.len(),
+        };
+        &self.nodes[self.ring[index].1]
+    }
+
+    pub fn is_local(&self, node: &NodeInfo) -> bool {
+        node.id == self.local_node_id
+    }
+
+    pub fn local_node_id(&self) -> &str {
+        &self.local_node_id
+    }
+}
+
+fn hash_key<T: Hash>(key: &T) -> u64 {
+    let mut hasher = DefaultHasher::new();
+    key.hash(&mut hasher);
+    hasher.finish()
+}
+
+/// Tracks, per room, which remote node ids this node is syncing that room with: each room that
+/// `ForwardingClient::ensure_forwarding` forwards is recorded against its owning node. Nothing in
+/// this module reads it to relay change events; replication runs over the forwarding connection.
+#[derive(Clone, Default)]
+pub struct RemoteBroadcastRegistry {
+    subscribers: Arc<Mutex<HashMap<String, HashSet<String>>>>,
+}
+
+impl RemoteBroadcastRegistry {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn subscribe(&self, room_name: &str, remote_node_id: &str) {
+        self.subscribers
+            .lock()
+            .expect("RemoteBroadcastRegistry mutex poisoned")
+            .entry(room_name.to_string())
+            .or_default()
+            .insert(remote_node_id.to_string());
+    }
+
+    pub fn unsubscribe(&self, room_name: &str, remote_node_id: &str) {
+        if let Some(set) = self.subscribers.lock().expect("RemoteBroadcastRegistry mutex poisoned").get_mut(room_name) {
+            set.remove(remote_node_id);
+        }
+    }
+
+    /// Remote node ids to relay a change in `room_name` to.
+    pub fn targets_for(&self, room_name: &str) -> HashSet<String> {
+        self.subscribers
+            .lock()
+            .expect("RemoteBroadcastRegistry mutex poisoned")
+            .get(room_name)
+            .cloned()
+            .unwrap_or_default()
+    }
+}
+
+/// A node-to-node client: on top of the ownership bookkeeping in `ClusterMetadata`, it holds live
+/// `ankurah_websocket_client` connections to remote nodes this node is forwarding traffic to, keyed
+/// by node id, and knows how to open one lazily via `ensure_forwarding`.
+pub struct ForwardingClient {
+    cluster: ClusterMetadata,
+    registry: RemoteBroadcastRegistry,
+    connections: Arc<Mutex<HashMap<String, WebsocketClient>>>,
+}
+
+impl ForwardingClient {
+    pub fn new(cluster: ClusterMetadata, registry: RemoteBroadcastRegistry) -> Self {
+        Self { cluster, registry, connections: Arc::new(Mutex::new(HashMap::new())) }
+    }
+
+    pub fn cluster(&self) -> &ClusterMetadata {
+        &self.cluster
+    }
+
+    pub fn registry(&self) -> &RemoteBroadcastRegistry {
+        &self.registry
+    }
+
+    /// Whether `room_name` is owned by this node. Callers use this to decide between handling a
+    /// request locally and forwarding it via `ensure_forwarding`.
+    pub fn owns_room(&self, room_name: &str) -> bool {
+        self.cluster.is_local(self.cluster.owner_of_room(room_name))
+    }
+
+    /// Makes sure this node is actively syncing with whichever node owns `room_name`, connecting
+    /// over websocket on first use and reusing the connection on later calls. A no-op if this node
+    /// already owns the room. Once connected, `local_node`'s own `Node::system` sync protocol
+    /// forwards local subscribes/mutates against that room to the owner (and replicates the
+    /// owner's changes back) the same way it already does for a browser client connected to this
+    /// node -- no per-message relaying needed here.
+    pub async fn ensure_forwarding<SE: StorageEngine + Send + Sync + 'static>(
+        &self,
+        local_node: &Node<SE, UserKeyPairAgent>,
+        room_name: &str,
+    ) -> anyhow::Result<()> {
+        let owner = self.cluster.owner_of_room(room_name);
+        if self.cluster.is_local(owner) {
+            return Ok(());
+        }
+
+        {
+            let connections = self.connections.lock().expect("ForwardingClient mutex poisoned");
+            if connections.contains_key(&owner.id) {
+                self.registry.subscribe(room_name, &owner.id);
+                return Ok(());
+            }
+        }
+
+        let client = WebsocketClient::new(local_node.clone(), &owner.addr)
+            .map_err(|e| anyhow::anyhow!("Failed to connect to node {}: {:?}", owner.id, e))?;
+        client
+            .ready()
+            .await
+            .map_err(|e| anyhow::anyhow!("Node {} connection never became ready: {:?}", owner.id, e))?;
+
+        self.registry.subscribe(room_name, &owner.id);
+        self.connections
+            .lock()
+            .expect("ForwardingClient mutex poisoned")
+            .insert(owner.id.clone(), client);
+
+        Ok(())
+    }
+}
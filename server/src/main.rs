@@ -1,22 +1,30 @@
+mod cluster;
+mod recovery;
+
 use ankurah::Node;
 use ankurah_storage_sled::SledStorageEngine;
 use ankurah_template_model::{MyContextData, Room, RoomView, UserKeyPairAgent};
 use ankurah_websocket_server::WebsocketServer;
 use anyhow::Result;
+use cluster::{ClusterMetadata, ForwardingClient, NodeInfo, RemoteBroadcastRegistry};
+use opentelemetry::trace::TracerProvider as _;
+use opentelemetry::KeyValue;
+use opentelemetry_otlp::WithExportConfig;
 use std::sync::Arc;
-use tracing::{info, Level};
+use tracing::{info, warn, Instrument, Level};
+use tracing_subscriber::{layer::SubscriberExt, util::SubscriberInitExt, Layer};
 
 #[tokio::main]
 async fn main() -> Result<()> {
-    tracing_subscriber::fmt().with_max_level(Level::INFO).init(); // initialize tracing
+    let _otel_guard = init_tracing()?;
 
     // Initialize storage engine
     let storage = SledStorageEngine::with_homedir_folder(".ankurah-template")?;
-    
+
     // Create agent and node
     let agent = UserKeyPairAgent::new_server();
     let node = Node::new_durable(Arc::new(storage), agent.clone());
-    
+
     // Initialize agent's root context now that node exists
     agent.initialize_root_context(node.clone());
 
@@ -25,15 +33,220 @@ async fn main() -> Result<()> {
         node.system.create().await?;
     }
 
-    // Ensure "General" room exists
-    ensure_general_room(&node).await?;
+    // Restore the persisted OPAQUE server setup (or mint and persist one on first boot) so a
+    // restart doesn't invalidate every password-enrolled User and IdentityRecovery row.
+    agent.rehydrate_opaque_setup().await?;
+
+    // Restore this node's own long-lived Ed25519 identity (or mint and persist one on first
+    // boot) so a restart doesn't invalidate every peer's `trust_peer_node` registration of this
+    // node. Must run after `rehydrate_opaque_setup`, which guarantees the shared `ServerConfig`
+    // row already exists.
+    agent.rehydrate_node_signing_key().await?;
+
+    // Rebuild the in-memory membership cache from storage so a restart doesn't lock everyone out
+    // of every room they'd already joined.
+    agent.rehydrate_memberships().await?;
+
+    // Same for the invite ledger, so restart doesn't reject every still-outstanding invite.
+    agent.rehydrate_invites().await?;
+
+    // And the admin set, derived from redeemed "admin" invites, so a restart doesn't silently
+    // revoke every admin grant.
+    agent.rehydrate_admins().await?;
+
+    // Resolve cluster membership before deciding whether to create "General": in a multi-node
+    // cluster, minting it unconditionally on every node would give a non-owner its own
+    // independent `Room` entity (a distinct `EntityId`) that never reconciles with the owner's.
+    let forwarding = ForwardingClient::new(cluster_metadata_from_env(), RemoteBroadcastRegistry::new());
+    info!(
+        owns_general = forwarding.owns_room("General"),
+        node_id = forwarding.cluster().local_node_id(),
+        "Cluster membership resolved"
+    );
+
+    if forwarding.owns_room("General") {
+        // This node owns "General": it's the one allowed to mint it if it doesn't exist yet.
+        ensure_general_room(&node).await?;
+    } else {
+        // Another node owns "General". Don't create a local copy -- start syncing with the owner
+        // now, rather than waiting for the periodic sweep below, so the real "General" room syncs
+        // in over this connection as soon as possible instead of a second, unreconciled one being
+        // minted here.
+        if let Err(e) = forwarding.ensure_forwarding(&node, "General").await {
+            warn!("Failed to connect to the node that owns 'General': {}", e);
+        }
+    }
 
+    tokio::spawn(recovery::run(node.clone(), agent.clone()));
+    tokio::spawn(flush_redeemed_invites_loop(agent.clone()));
+    tokio::spawn(ensure_forwarding_for_all_rooms_loop(node.clone(), forwarding));
+
+    // `ankurah_websocket_server::WebsocketServer::run` doesn't expose a per-connection hook, so
+    // per-connection activity can't be spanned from here; the transaction commits in
+    // `ensure_general_room` and `UserKeyPairAgent::mint_invite`/`flush_redeemed_invites` (model
+    // crate) are spanned instead, plus this span covering the server's run loop as a whole. The
+    // commits made by the `recovery` responder and by the startup rehydration steps have no span.
     let mut server = WebsocketServer::new(node);
-    server.run("0.0.0.0:9797").await?;
+    let run_span = tracing::info_span!("websocket_server.run", addr = "0.0.0.0:9797");
+    server.run("0.0.0.0:9797").instrument(run_span).await?;
 
     Ok(())
 }
 
+/// RAII handle for the OTLP exporter's background batch processor. Dropping it (at the end of
+/// `main`) flushes any spans still buffered, so a clean shutdown doesn't lose the tail of a trace.
+struct OtelGuard {
+    provider: Option<opentelemetry_sdk::trace::TracerProvider>,
+}
+
+impl Drop for OtelGuard {
+    fn drop(&mut self) {
+        if let Some(provider) = self.provider.take() {
+            if let Err(e) = provider.shutdown() {
+                eprintln!("Error shutting down OTLP tracer provider: {e}");
+            }
+        }
+    }
+}
+
+/// Installs the `fmt` layer we've always had, plus an OTLP trace exporter layer when
+/// `OTEL_EXPORTER_OTLP_ENDPOINT` is set. Leaving the endpoint unset (the default for local/dev
+/// runs) skips creating the exporter entirely, so there's no OTLP connection attempt, no batch
+/// processor thread, and no per-span export overhead in that case.
+fn init_tracing() -> Result<OtelGuard> {
+    let fmt_layer = tracing_subscriber::fmt::layer().with_filter(
+        tracing_subscriber::filter::LevelFilter::from_level(Level::INFO),
+    );
+
+    let (otel_layer, provider) = match std::env::var("OTEL_EXPORTER_OTLP_ENDPOINT") {
+        Ok(endpoint) => {
+            let service_name = std::env::var("OTEL_SERVICE_NAME")
+                .unwrap_or_else(|_| "ankurah-template-server".to_string());
+
+            let exporter = opentelemetry_otlp::SpanExporter::builder()
+                .with_tonic()
+                .with_endpoint(endpoint)
+                .build()?;
+
+            let provider = opentelemetry_sdk::trace::TracerProvider::builder()
+                .with_batch_exporter(exporter, opentelemetry_sdk::runtime::Tokio)
+                .with_resource(opentelemetry_sdk::Resource::new(vec![KeyValue::new(
+                    "service.name",
+                    service_name,
+                )]))
+                .build();
+
+            let tracer = provider.tracer("ankurah-template-server");
+            (
+                Some(tracing_opentelemetry::layer().with_tracer(tracer)),
+                Some(provider),
+            )
+        }
+        Err(_) => (None, None),
+    };
+
+    tracing_subscriber::registry()
+        .with(fmt_layer)
+        .with(otel_layer)
+        .init();
+
+    Ok(OtelGuard { provider })
+}
+
+/// Builds cluster config from `CLUSTER_NODES` (`id=ws://host:port` pairs, comma-separated) and
+/// `CLUSTER_LOCAL_NODE_ID`. Leaving both unset (the default) yields a single-node cluster that
+/// owns every room locally, matching today's single-process behavior.
+fn cluster_metadata_from_env() -> ClusterMetadata {
+    let local_node_id = std::env::var("CLUSTER_LOCAL_NODE_ID").unwrap_or_else(|_| "local".to_string());
+
+    let nodes = match std::env::var("CLUSTER_NODES") {
+        Ok(raw) => raw
+            .split(',')
+            .filter_map(|entry| {
+                let (id, addr) = entry.split_once('=')?;
+                Some(NodeInfo { id: id.trim().to_string(), addr: addr.trim().to_string() })
+            })
+            .collect::<Vec<_>>(),
+        Err(_) => Vec::new(),
+    };
+
+    if nodes.is_empty() {
+        ClusterMetadata::single_node(local_node_id)
+    } else {
+        ClusterMetadata::new(nodes, local_node_id)
+    }
+}
+
+/// How often to persist invite redemptions recorded by `check_event`'s synchronous ledger back to
+/// the `Invite` entity's `redeemed_by` field (see `UserKeyPairAgent::flush_redeemed_invites`).
+/// Self-registration is interactive but infrequent, so a short fixed interval is fine here too.
+const INVITE_FLUSH_INTERVAL: std::time::Duration = std::time::Duration::from_millis(500);
+
+/// Runs forever, persisting invite redemptions as they happen. Spawned once from `main` alongside
+/// the OPAQUE challenge responder. Woken either by the fixed interval or by
+/// `UserKeyPairAgent::wait_for_admin_grant`, whichever comes first, so an admin grant doesn't sit
+/// unpersisted for the full interval -- a crash in that window would let `rehydrate_invites` see
+/// the invite as still-unredeemed and let it grant admin a second time after restart.
+async fn flush_redeemed_invites_loop(agent: UserKeyPairAgent) {
+    loop {
+        tokio::select! {
+            _ = tokio::time::sleep(INVITE_FLUSH_INTERVAL) => {}
+            _ = agent.wait_for_admin_grant() => {}
+        }
+        if let Err(e) = agent.flush_redeemed_invites().await {
+            warn!("Failed to flush redeemed invites: {}", e);
+        }
+    }
+}
+
+/// How often to sweep locally-known rooms for ones this node doesn't own (see
+/// `ensure_forwarding_for_all_rooms_loop`). A room is only local-but-unowned for the short window
+/// between a client creating it on this node (e.g. via a `CreateRoom`-scoped token) and this sweep
+/// picking it up, so a short fixed interval keeps that window small without needing per-request
+/// routing.
+const ROOM_FORWARDING_SWEEP_INTERVAL: std::time::Duration = std::time::Duration::from_secs(2);
+
+/// Runs forever, forwarding every room this node knows about but doesn't own. "General" is
+/// connected eagerly at boot (see `main`) so it's available immediately; this sweep is what
+/// extends forwarding to every other room instead of leaving it "General"-only; `Room` rows can
+/// also arrive and need forwarding live on an already-running node (e.g. a client with a
+/// `CreateRoom`-scoped token creates one against this node, but the consistent-hash ring says a
+/// different node owns it), and there's still no per-request routing hook in
+/// `ankurah_websocket_server` to react to that the instant it happens (see cluster.rs), so this
+/// polls instead. `ForwardingClient::ensure_forwarding` is a no-op for rooms already forwarded or
+/// owned locally, so re-sweeping the same rooms every tick costs nothing.
+async fn ensure_forwarding_for_all_rooms_loop(
+    node: Node<SledStorageEngine, UserKeyPairAgent>,
+    forwarding: ForwardingClient,
+) {
+    let context = node.context_async(MyContextData::Root).await;
+    loop {
+        tokio::time::sleep(ROOM_FORWARDING_SWEEP_INTERVAL).await;
+
+        let rooms = match context.fetch::<RoomView>("true").await {
+            Ok(rooms) => rooms,
+            Err(e) => {
+                warn!("Failed to poll rooms for forwarding: {}", e);
+                continue;
+            }
+        };
+
+        for room in rooms {
+            let name = match room.name() {
+                Ok(name) => name,
+                Err(e) => {
+                    warn!("Failed to read room name while sweeping for forwarding: {}", e);
+                    continue;
+                }
+            };
+            if let Err(e) = forwarding.ensure_forwarding(&node, &name).await {
+                warn!("Failed to connect to the node that owns '{}': {}", name, e);
+            }
+        }
+    }
+}
+
+#[tracing::instrument(skip(node))]
 async fn ensure_general_room(node: &Node<SledStorageEngine, UserKeyPairAgent>) -> Result<()> {
     let context = node.context_async(MyContextData::Root).await;
 
@@ -48,7 +261,9 @@ async fn ensure_general_room(node: &Node<SledStorageEngine, UserKeyPairAgent>) -
             name: "General".to_string(),
         })
         .await?;
-        trx.commit().await?;
+        trx.commit()
+            .instrument(tracing::info_span!("transaction.commit", collection = "room"))
+            .await?;
 
         info!("'General' room created");
     } else {
@@ -0,0 +1,154 @@
+//! Background responder for `OpaqueChallenge` and `TokenIssuance` entities.
+//!
+//! A browser client has no transport to the server beyond the entity/subscription system, so the
+//! OPAQUE registration and login handshakes needed for `recover_identity` are relayed as
+//! `OpaqueChallenge` entities instead of a dedicated RPC: a client creates one with its outbound
+//! protocol message in `request`, and this loop is the only thing that ever answers it (the
+//! model's `check_event` policy rejects any other writer of `response`). `TokenIssuance` is
+//! answered the same way, for the same reason: minting a bearer token needs the server's own
+//! `token_secret`, which a client has no access to. There's no subscription API available to react
+//! to new rows immediately, so this polls at a short, fixed interval -- acceptable latency for
+//! interactive, infrequent operations like these.
+
+use ankurah::proto::EntityId;
+use ankurah::Node;
+use ankurah_storage_sled::SledStorageEngine;
+use ankurah_template_model::{
+    opaque_auth::TemplateCipherSuite, IdentityRecoveryView, MyContextData, OpaqueChallengeView,
+    TokenIssuanceView, UserKeyPairAgent, UserView,
+};
+use base64::{engine::general_purpose::STANDARD as BASE64, Engine};
+use opaque_ke::{
+    CredentialFinalization, CredentialRequest, RegistrationRequest, RegistrationUpload,
+    ServerRegistration,
+};
+use std::time::Duration;
+use tracing::{info, warn};
+
+const POLL_INTERVAL: Duration = Duration::from_millis(200);
+
+/// Runs forever, answering `OpaqueChallenge`s and `TokenIssuance` requests as they appear. Spawned
+/// once from `main` alongside the websocket server.
+pub async fn run(node: Node<SledStorageEngine, UserKeyPairAgent>, agent: UserKeyPairAgent) {
+    let context = node.context_async(MyContextData::Root).await;
+
+    loop {
+        tokio::time::sleep(POLL_INTERVAL).await;
+
+        let pending = match context.fetch::<OpaqueChallengeView>("response = ''").await {
+            Ok(pending) => pending,
+            Err(e) => {
+                warn!("Failed to poll OPAQUE challenges: {}", e);
+                continue;
+            }
+        };
+
+        for challenge in pending {
+            if let Err(e) = answer_challenge(&context, &agent, &challenge).await {
+                warn!("Failed to answer OPAQUE challenge: {}", e);
+            }
+        }
+
+        let pending_tokens = match context.fetch::<TokenIssuanceView>("token = ''").await {
+            Ok(pending) => pending,
+            Err(e) => {
+                warn!("Failed to poll token issuance requests: {}", e);
+                continue;
+            }
+        };
+
+        for request in pending_tokens {
+            if let Err(e) = answer_token_issuance(&context, &agent, &request).await {
+                warn!("Failed to answer token issuance request: {}", e);
+            }
+        }
+    }
+}
+
+async fn answer_token_issuance(
+    context: &ankurah::core::context::Context,
+    agent: &UserKeyPairAgent,
+    request: &TokenIssuanceView,
+) -> anyhow::Result<()> {
+    let user_id = EntityId::from_base64(&request.user()?)?;
+    let token = agent.issue_token(user_id, request.ttl_secs()?, request.scope()? as u8);
+
+    let transaction = context.begin();
+    let request_mut = transaction.edit(request).await?;
+    request_mut.token().set(&BASE64.encode(token))?;
+    transaction.commit().await?;
+
+    info!("Answered token issuance request for user {}", request.user()?);
+    Ok(())
+}
+
+async fn answer_challenge(
+    context: &ankurah::core::context::Context,
+    agent: &UserKeyPairAgent,
+    challenge: &OpaqueChallengeView,
+) -> anyhow::Result<()> {
+    let handle = challenge.handle()?;
+    let kind = challenge.kind()?;
+    let request_bytes = BASE64.decode(challenge.request()?)?;
+
+    let response_bytes = match kind.as_str() {
+        "register-start" => {
+            let request = RegistrationRequest::<TemplateCipherSuite>::deserialize(&request_bytes)?;
+            agent
+                .opaque_register_start(request, handle.as_bytes())?
+                .serialize()
+                .to_vec()
+        }
+        "register-finish" => {
+            let upload = RegistrationUpload::<TemplateCipherSuite>::deserialize(&request_bytes)?;
+            agent.opaque_register_finish(upload)?
+        }
+        "login-start" => {
+            let password_file = if let Some(record) = context
+                .fetch::<IdentityRecoveryView>(&format!("handle = '{}'", handle))
+                .await?
+                .into_iter()
+                .next()
+            {
+                Some(ServerRegistration::<TemplateCipherSuite>::deserialize(
+                    &BASE64.decode(record.envelope()?)?,
+                )?)
+            } else if let Ok(user_id) = EntityId::from_base64(&handle) {
+                // Not a recovery handle -- `register_password`/`login_with_password` in
+                // wasm-bindings address the `User` directly by id instead of a separate
+                // `IdentityRecovery` row, so fall back to its `password_envelope`.
+                match context.get::<UserView>(user_id).await {
+                    Ok(user) if !user.password_envelope()?.is_empty() => {
+                        Some(ServerRegistration::<TemplateCipherSuite>::deserialize(
+                            &BASE64.decode(user.password_envelope()?)?,
+                        )?)
+                    }
+                    _ => None, // unknown handle: still run the protocol so probing can't tell the difference
+                }
+            } else {
+                None // unknown handle: still run the protocol so probing can't tell the difference
+            };
+            let request = CredentialRequest::<TemplateCipherSuite>::deserialize(&request_bytes)?;
+            let result = agent.opaque_login_start(password_file, handle.as_bytes(), request)?;
+            agent.store_opaque_login_session(handle.clone(), result.state);
+            result.message.serialize().to_vec()
+        }
+        "login-finish" => {
+            let state = agent
+                .take_opaque_login_session(&handle)
+                .ok_or_else(|| anyhow::anyhow!("No in-progress login session for handle {}", handle))?;
+            let finalization = CredentialFinalization::<TemplateCipherSuite>::deserialize(&request_bytes)?;
+            agent.opaque_login_finish(state, finalization)?;
+            vec![1u8] // no further data to send; presence of a response just signals completion
+        }
+        other => anyhow::bail!("Unknown OPAQUE challenge kind: {}", other),
+    };
+
+    let transaction = context.begin();
+    let challenge_mut = transaction.edit(challenge).await?;
+    challenge_mut.response().set(&BASE64.encode(response_bytes))?;
+    transaction.commit().await?;
+
+    info!("Answered OPAQUE challenge {} ({})", handle, kind);
+    Ok(())
+}
@@ -0,0 +1,173 @@
+//! Reconnection supervisor for the client's websocket connection to the server node, plus an
+//! offline mutation queue for commits made through this crate's own mutation wrappers (e.g.
+//! `join_room`) while disconnected.
+//!
+//! A `Context` commit is written to the local `IndexedDBStorageEngine` regardless of connection
+//! state, so nothing made through the generic `ankurah` `Context`/`Transaction` JS bindings (which
+//! this crate has no visibility into) is ever lost offline; what's missing there is confirmation
+//! that it reached the server, which is entirely up to `Node::system`'s own sync protocol and not
+//! something this crate has a hook into. `OfflineMutationQueue` covers the part that is ours to
+//! own: a mutation wrapper in `lib.rs` that fails to commit enqueues a retry closure here instead
+//! of surfacing the failure, and `ready()` drains the queue (in FIFO order, so nothing made while
+//! offline is replayed out of order) before it reports the client ready again.
+
+use std::cell::RefCell;
+use std::collections::VecDeque;
+use std::future::Future;
+use std::pin::Pin;
+use std::rc::Rc;
+
+use ankurah::Node;
+use ankurah_storage_indexeddb_wasm::IndexedDBStorageEngine;
+use ankurah_template_model::UserKeyPairAgent;
+use ankurah_websocket_client_wasm::WebsocketClient;
+use send_wrapper::SendWrapper;
+use tracing::{info, warn};
+use wasm_bindgen::JsValue;
+
+/// Initial retry delay. Doubles on each consecutive failure up to `MAX_BACKOFF_MS`.
+const INITIAL_BACKOFF_MS: u32 = 250;
+/// Upper bound on the retry delay, so a long outage still retries every 30s rather than less often.
+const MAX_BACKOFF_MS: u32 = 30_000;
+
+/// How often the background supervisor re-checks `ready()` to notice a connection that dropped
+/// after the initial handshake (there's no push notification for this from `WebsocketClient`).
+pub const HEALTH_CHECK_INTERVAL_MS: u32 = 5_000;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ConnectionState {
+    Disconnected,
+    Connecting,
+    Connected,
+}
+
+impl ConnectionState {
+    fn as_str(self) -> &'static str {
+        match self {
+            ConnectionState::Disconnected => "Disconnected",
+            ConnectionState::Connecting => "Connecting",
+            ConnectionState::Connected => "Connected",
+        }
+    }
+}
+
+impl From<ConnectionState> for JsValue {
+    fn from(state: ConnectionState) -> JsValue {
+        JsValue::from_str(state.as_str())
+    }
+}
+
+pub async fn sleep_ms(ms: u32) {
+    let promise = js_sys::Promise::new(&mut |resolve, _reject| {
+        let window = web_sys::window().expect("No window available");
+        let _ = window.set_timeout_with_callback_and_timeout_and_arguments_0(&resolve, ms as i32);
+    });
+    let _ = wasm_bindgen_futures::JsFuture::from(promise).await;
+}
+
+/// Jittered delay for the given retry attempt (0-indexed), capped at `MAX_BACKOFF_MS`. Subtracts
+/// (rather than subtracting then re-adding) a random amount up to half the jitter budget, so
+/// clients disconnected by the same outage spread their retries out instead of all landing on
+/// exactly `capped` in lockstep.
+fn backoff_delay_ms(attempt: u32) -> u32 {
+    let base = INITIAL_BACKOFF_MS.saturating_mul(1u32.checked_shl(attempt).unwrap_or(u32::MAX));
+    let capped = base.min(MAX_BACKOFF_MS);
+    let jitter = (js_sys::Math::random() * (capped as f64) * 0.25) as u32;
+    capped.saturating_sub(jitter / 2)
+}
+
+/// Connects (retrying with exponential backoff + jitter on failure), reporting each transition
+/// through `on_state_change`, and returns the live client once connected. This does not watch
+/// for later drops; `start()` in `lib.rs` runs its own health-check loop that calls this again.
+pub async fn connect_with_backoff<F: Fn(ConnectionState)>(
+    node: &Node<IndexedDBStorageEngine, UserKeyPairAgent>,
+    ws_url: &str,
+    on_state_change: &F,
+) -> WebsocketClient {
+    let mut attempt = 0u32;
+    loop {
+        on_state_change(ConnectionState::Connecting);
+        match WebsocketClient::new(node.clone(), ws_url) {
+            Ok(client) => {
+                if client.ready().await.is_ok() {
+                    on_state_change(ConnectionState::Connected);
+                    return client;
+                }
+                warn!("Websocket connected but never became ready; retrying");
+            }
+            Err(e) => {
+                warn!("Failed to establish websocket connection: {:?}", e);
+            }
+        }
+
+        on_state_change(ConnectionState::Disconnected);
+        let delay = backoff_delay_ms(attempt);
+        info!("Reconnecting in {}ms (attempt {})", delay, attempt + 1);
+        sleep_ms(delay).await;
+        attempt = attempt.saturating_add(1);
+    }
+}
+
+/// Holds the currently-live client so `ws_client()`/`ready()` can keep returning a fresh handle
+/// across reconnects rather than being pinned to the first connection attempt.
+pub struct ClientCell {
+    inner: SendWrapper<RefCell<Option<WebsocketClient>>>,
+}
+
+impl ClientCell {
+    pub fn new() -> Self {
+        Self {
+            inner: SendWrapper::new(RefCell::new(None)),
+        }
+    }
+
+    pub fn set(&self, client: WebsocketClient) {
+        *self.inner.borrow_mut() = Some(client);
+    }
+
+    pub fn get(&self) -> Option<WebsocketClient> {
+        self.inner.borrow().clone()
+    }
+}
+
+/// A retryable mutation: re-running it must be safe (it recomputes its own `Context` and retries
+/// its own commit from scratch), since `drain` replays the same closure on every attempt until it
+/// succeeds.
+type QueuedMutation = Rc<dyn Fn() -> Pin<Box<dyn Future<Output = Result<(), JsValue>>>>>;
+
+/// Ordered outbox for mutations that failed to commit while disconnected (see the module doc).
+/// `lib.rs`'s mutation wrappers enqueue a retry closure here instead of surfacing the failure;
+/// `drain` replays them in the order they were enqueued, so a reconnect doesn't reorder mutations
+/// made while offline.
+#[derive(Clone)]
+pub struct OfflineMutationQueue {
+    inner: Rc<RefCell<VecDeque<QueuedMutation>>>,
+}
+
+impl OfflineMutationQueue {
+    pub fn new() -> Self {
+        Self { inner: Rc::new(RefCell::new(VecDeque::new())) }
+    }
+
+    pub fn enqueue(&self, mutation: QueuedMutation) {
+        self.inner.borrow_mut().push_back(mutation);
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.inner.borrow().is_empty()
+    }
+
+    /// Replays queued mutations in FIFO order. Stops (leaving the rest queued) at the first one
+    /// that still fails, so a connection that drops again mid-drain doesn't reorder anything.
+    pub async fn drain(&self) {
+        loop {
+            let next = self.inner.borrow().front().cloned();
+            let Some(mutation) = next else { return };
+            if let Err(e) = mutation().await {
+                warn!("Queued mutation still failing after reconnect, will retry next reconnect: {:?}", e);
+                return;
+            }
+            self.inner.borrow_mut().pop_front();
+        }
+    }
+}
@@ -1,4 +1,4 @@
-use std::{panic, sync::Arc};
+use std::{panic, rc::Rc, sync::Arc};
 
 use ankurah::core::context::Context;
 use ankurah::Node;
@@ -11,9 +11,17 @@ use lazy_static::lazy_static;
 use once_cell::sync::OnceCell;
 use rand::rngs::OsRng;
 use send_wrapper::SendWrapper;
-use tracing::{error, info};
+use tracing::{error, info, warn};
 use wasm_bindgen::{prelude::wasm_bindgen, JsValue};
-use web_sys::{window, Storage};
+use web_sys::{window, Storage, UrlSearchParams};
+
+mod connection;
+mod recovery;
+mod secure_key_storage;
+
+use ankurah_template_model::opaque_auth::TemplateCipherSuite;
+use connection::{connect_with_backoff, ClientCell, ConnectionState, OfflineMutationQueue};
+use opaque_ke::{CredentialResponse, RegistrationResponse};
 
 pub use ankurah_template_model::*;
 
@@ -22,14 +30,24 @@ pub use ankurah_signals::{react::*, JsValueMut, JsValueRead};
 
 lazy_static! {
     static ref NODE: OnceCell<Node<IndexedDBStorageEngine, UserKeyPairAgent>> = OnceCell::new();
-    static ref CLIENT: OnceCell<SendWrapper<WebsocketClient>> = OnceCell::new();
+    static ref CLIENT: ClientCell = ClientCell::new();
+    /// Mutations that failed to commit while disconnected, retried in order once reconnected. See
+    /// `connection::OfflineMutationQueue`.
+    static ref OFFLINE_QUEUE: SendWrapper<OfflineMutationQueue> = SendWrapper::new(OfflineMutationQueue::new());
     static ref NOTIFY: tokio::sync::Notify = tokio::sync::Notify::new();
     static ref USER_KEYPAIR: OnceCell<SigningKey> = OnceCell::new();
     static ref CURRENT_USER: OnceCell<SendWrapper<ankurah_signals::JsValueMut>> = OnceCell::new();
+    static ref CONNECTION_STATE: OnceCell<SendWrapper<ankurah_signals::JsValueMut>> = OnceCell::new();
+    /// Keeps the websocket connections opened by `scoped_ctx`/`token_ctx` alive for the lifetime
+    /// of the page. Each such session is its own `Node`/agent independent of the primary
+    /// `NODE`/`CLIENT`, so nothing else is holding these otherwise.
+    static ref SCOPED_CLIENTS: SendWrapper<std::cell::RefCell<Vec<WebsocketClient>>> =
+        SendWrapper::new(std::cell::RefCell::new(Vec::new()));
 }
 
 const STORAGE_KEY_USER_ID: &str = "ankurah_template_user_id";
-const STORAGE_KEY_PRIVATE_KEY: &str = "ankurah_template_private_key";
+const STORAGE_KEY_WRAPPED_KEY: &str = "ankurah_template_wrapped_key";
+const STORAGE_KEY_WRAPPED_KEY_IV: &str = "ankurah_template_wrapped_key_iv";
 
 #[wasm_bindgen(start)]
 pub async fn start() -> Result<(), JsValue> {
@@ -42,7 +60,7 @@ pub async fn start() -> Result<(), JsValue> {
     panic::set_hook(Box::new(console_error_panic_hook::hook));
 
     // Load or generate user keypair
-    let user_keypair = load_or_generate_user_keypair()?;
+    let user_keypair = load_or_generate_user_keypair().await?;
     if let Err(_) = USER_KEYPAIR.set(user_keypair.clone()) {
         error!("Failed to set user keypair");
     }
@@ -63,14 +81,43 @@ pub async fn start() -> Result<(), JsValue> {
         .map_err(|e| JsValue::from_str(&format!("Failed to get hostname: {:?}", e)))?;
     let ws_url = format!("ws://{}:9797", hostname);
 
-    let connector = WebsocketClient::new(node.clone(), &ws_url)?;
+    if let Err(_) = CONNECTION_STATE.set(SendWrapper::new(ankurah_signals::JsValueMut::new(
+        JsValue::from(ConnectionState::Connecting),
+    ))) {
+        error!("Failed to set connection state signal");
+    }
+    let set_connection_state = |state: ConnectionState| {
+        if let Some(signal) = CONNECTION_STATE.get() {
+            signal.set(JsValue::from(state));
+        }
+    };
+
+    let connector = connect_with_backoff(&node, &ws_url, &set_connection_state).await;
     node.system.wait_system_ready().await;
-    if let Err(_) = NODE.set(node) {
+    if let Err(_) = NODE.set(node.clone()) {
         error!("Failed to set node");
     }
-    if let Err(_) = CLIENT.set(SendWrapper::new(connector)) {
-        error!("Failed to set connector");
-    }
+    CLIENT.set(connector);
+    OFFLINE_QUEUE.drain().await;
+
+    // Once the initial connection succeeds, keep polling `ready()` in the background and
+    // reconnect with backoff if it ever comes back unhealthy, so a dropped connection doesn't
+    // leave the app silently desynced for the rest of the session.
+    wasm_bindgen_futures::spawn_local(async move {
+        loop {
+            connection::sleep_ms(connection::HEALTH_CHECK_INTERVAL_MS).await;
+            let unhealthy = match CLIENT.get() {
+                Some(client) => client.ready().await.is_err(),
+                None => true,
+            };
+            if unhealthy {
+                set_connection_state(ConnectionState::Disconnected);
+                let connector = connect_with_backoff(&node, &ws_url, &set_connection_state).await;
+                CLIENT.set(connector);
+                OFFLINE_QUEUE.drain().await;
+            }
+        }
+    });
 
     // Initialize current user signal
     let initial_value = JsValue::NULL;
@@ -79,8 +126,13 @@ pub async fn start() -> Result<(), JsValue> {
         error!("Failed to set current user signal");
     }
 
+    // An invitation token for first-time registration, if present, is read from the `invite`
+    // query param so a deployment can be invite-gated without any JS-side wiring; callers that
+    // want to supply it later (e.g. pasted in from an email) can use `redeem_invite` instead.
+    let invite_token = invite_token_from_location(&window)?;
+
     // Initialize user (blocking) before notifying that system is ready
-    match init_user_internal().await {
+    match init_user_internal(invite_token).await {
         Ok(user_view) => {
             if let Some(user_signal) = CURRENT_USER.get() {
                 user_signal.set(JsValue::from(user_view));
@@ -110,29 +162,56 @@ fn get_local_storage() -> Result<Storage, JsValue> {
         .ok_or_else(|| JsValue::from_str("localStorage not available"))
 }
 
-fn load_or_generate_user_keypair() -> Result<SigningKey, JsValue> {
+/// Reads an `invite` query param off the current page URL, if present.
+fn invite_token_from_location(window: &web_sys::Window) -> Result<Option<String>, JsValue> {
+    let search = window
+        .location()
+        .search()
+        .map_err(|e| JsValue::from_str(&format!("Failed to read location search: {:?}", e)))?;
+    let params = UrlSearchParams::new_with_str(&search)?;
+    Ok(params.get("invite"))
+}
+
+/// Loads the user's signing key, unwrapping it with the IndexedDB-backed WebCrypto key, or
+/// generates a new one on first run. Only the AES-GCM ciphertext and IV ever touch localStorage;
+/// the wrapping key itself is non-extractable, so a script reading localStorage gets nothing usable.
+async fn load_or_generate_user_keypair() -> Result<SigningKey, JsValue> {
     let storage = get_local_storage()?;
 
-    // Try to load existing key
-    if let Some(key_b64) = storage
-        .get_item(STORAGE_KEY_PRIVATE_KEY)
-        .map_err(|e| JsValue::from_str(&format!("Failed to read private key: {:?}", e)))?
-    {
-        let key_bytes = BASE64
-            .decode(&key_b64)
-            .map_err(|e| JsValue::from_str(&format!("Failed to decode private key: {}", e)))?;
-        let key_array: [u8; 32] = key_bytes
+    // Try to load and unwrap an existing key
+    if let (Some(ciphertext_b64), Some(iv_b64)) = (
+        storage
+            .get_item(STORAGE_KEY_WRAPPED_KEY)
+            .map_err(|e| JsValue::from_str(&format!("Failed to read wrapped key: {:?}", e)))?,
+        storage
+            .get_item(STORAGE_KEY_WRAPPED_KEY_IV)
+            .map_err(|e| JsValue::from_str(&format!("Failed to read wrapped key IV: {:?}", e)))?,
+    ) {
+        let ciphertext = BASE64
+            .decode(&ciphertext_b64)
+            .map_err(|e| JsValue::from_str(&format!("Failed to decode wrapped key: {}", e)))?;
+        let iv_bytes = BASE64
+            .decode(&iv_b64)
+            .map_err(|e| JsValue::from_str(&format!("Failed to decode wrapped key IV: {}", e)))?;
+        let iv: [u8; 12] = iv_bytes
             .try_into()
-            .map_err(|_| JsValue::from_str("Invalid private key length"))?;
-        return Ok(SigningKey::from_bytes(&key_array));
+            .map_err(|_| JsValue::from_str("Invalid wrapped key IV length"))?;
+
+        let seed = secure_key_storage::unwrap_seed(&ciphertext, &iv).await?;
+        return Ok(SigningKey::from_bytes(&seed));
     }
 
-    // Generate new keypair
+    // Generate new keypair, wrap the seed, and persist only the ciphertext + IV
     let user_keypair = SigningKey::generate(&mut OsRng);
-    let key_b64 = BASE64.encode(user_keypair.to_bytes());
+    let seed = user_keypair.to_bytes();
+    let (ciphertext, iv) = secure_key_storage::wrap_seed(&seed).await?;
+
     storage
-        .set_item(STORAGE_KEY_PRIVATE_KEY, &key_b64)
-        .map_err(|e| JsValue::from_str(&format!("Failed to store private key: {:?}", e)))?;
+        .set_item(STORAGE_KEY_WRAPPED_KEY, &BASE64.encode(&ciphertext))
+        .map_err(|e| JsValue::from_str(&format!("Failed to store wrapped key: {:?}", e)))?;
+    storage
+        .set_item(STORAGE_KEY_WRAPPED_KEY_IV, &BASE64.encode(iv))
+        .map_err(|e| JsValue::from_str(&format!("Failed to store wrapped key IV: {:?}", e)))?;
 
     Ok(user_keypair)
 }
@@ -155,7 +234,7 @@ pub fn ctx() -> Result<Context, JsValue> {
 
 #[wasm_bindgen]
 pub fn ws_client() -> WebsocketClient {
-    (**CLIENT.get().expect("Client not initialized")).clone()
+    CLIENT.get().expect("Client not initialized")
 }
 
 #[wasm_bindgen]
@@ -167,7 +246,28 @@ pub async fn ready() -> Result<(), JsValue> {
             CLIENT.get().expect("Client not initialized").ready().await
         }
     }
-    .map_err(|_| JsValue::from_str("Failed to connect to server"))
+    .map_err(|_| JsValue::from_str("Failed to connect to server"))?;
+
+    // Don't report ready while mutations made offline are still waiting to be retried -- a caller
+    // that treats `ready()` as "safe to assume my writes landed" would otherwise be misled.
+    if !OFFLINE_QUEUE.is_empty() {
+        OFFLINE_QUEUE.drain().await;
+    }
+    if !OFFLINE_QUEUE.is_empty() {
+        return Err(JsValue::from_str("Connected, but offline mutations are still pending"));
+    }
+
+    Ok(())
+}
+
+/// The client's current connection status (`"Disconnected"`/`"Connecting"`/`"Connected"`), so
+/// React can show a status indicator instead of syncing going silently stale.
+#[wasm_bindgen]
+pub fn connection_state() -> JsValueRead {
+    CONNECTION_STATE
+        .get()
+        .map(|state_signal| state_signal.read())
+        .expect("Connection state not initialized")
 }
 
 #[wasm_bindgen]
@@ -178,7 +278,7 @@ pub fn current_user() -> JsValueRead {
         .expect("Current user not initialized")
 }
 
-async fn init_user_internal() -> Result<UserView, JsValue> {
+async fn init_user_internal(invite_token: Option<String>) -> Result<UserView, JsValue> {
     let storage = get_local_storage()?;
     let node = get_node();
     let user_keypair = USER_KEYPAIR
@@ -211,9 +311,12 @@ async fn init_user_internal() -> Result<UserView, JsValue> {
     info!("Creating new user with public key: {}", pub_key);
 
     // We need to create a temporary context for user creation
-    // Use Anonymous context for self-registration since we don't have a user yet
+    // Use Anonymous context for self-registration since we don't have a user yet. The server
+    // rejects this unless `invite_token` is a valid, unexpired, unconsumed invite code.
     let temp_context = node
-        .context(MyContextData::Anonymous)
+        .context(MyContextData::Anonymous {
+            invite_code: invite_token,
+        })
         .map_err(|e| JsValue::from_str(&e.to_string()))?;
 
     let transaction = temp_context.begin();
@@ -224,6 +327,7 @@ async fn init_user_internal() -> Result<UserView, JsValue> {
                 (web_sys::js_sys::Math::random() * 10000.0) as i32
             ),
             pub_key: String::new(), // Initialize empty, will set below
+            password_envelope: String::new(), // Empty until register_password enrolls a password
         })
         .await
         .map_err(|e| JsValue::from_str(&format!("Failed to create user: {}", e)))?;
@@ -258,8 +362,576 @@ async fn init_user_internal() -> Result<UserView, JsValue> {
         .await
         .map_err(|e| JsValue::from_str(&format!("Failed to fetch created user: {}", e)))?;
 
+    // Auto-join the "General" room every fresh account starts in, so a newly registered user
+    // isn't immediately locked out of the room the template centers on (membership-gated ACLs
+    // mean a user in zero rooms can't read or post anywhere).
+    if let Err(e) = join_room_by_name(&user_context, &user_id.to_base64(), "General").await {
+        error!("Failed to auto-join 'General' room for new user: {:?}", e);
+    }
+
     Ok(user_view)
 }
 
+/// Self-joins `room_name` as a `"member"`, the same grant `check_event` allows any authenticated
+/// user to make for themselves in a room they're not in yet.
+async fn join_room_by_name(context: &Context, user_id_b64: &str, room_name: &str) -> Result<(), JsValue> {
+    let rooms = context
+        .fetch::<RoomView>(&format!("name = '{}'", room_name))
+        .await
+        .map_err(|e| JsValue::from_str(&format!("Failed to look up room: {}", e)))?;
+    let room = rooms
+        .into_iter()
+        .next()
+        .ok_or_else(|| JsValue::from_str("Room not found"))?;
+    create_membership(context, user_id_b64, &room.id().to_base64()).await
+}
+
+async fn create_membership(context: &Context, user_id_b64: &str, room_id_b64: &str) -> Result<(), JsValue> {
+    let transaction = context.begin();
+    transaction
+        .create(&RoomMembership {
+            user: user_id_b64.to_string(),
+            room: room_id_b64.to_string(),
+            role: "member".to_string(),
+        })
+        .await
+        .map_err(|e| JsValue::from_str(&format!("Failed to create room membership: {}", e)))?;
+    transaction
+        .commit()
+        .await
+        .map_err(|e| JsValue::from_str(&format!("Failed to commit room membership: {}", e)))?;
+
+    Ok(())
+}
+
+/// Self-joins a room by id as a `"member"`. Exposed as a primitive for the React app to call for
+/// any room beyond the auto-joined "General" one; `check_event` is the actual authority on whether
+/// the grant is allowed (a user may self-grant `"member"` in any room, while higher roles need an
+/// existing owner/moderator of that room to grant them).
+///
+/// If the commit fails (e.g. because the client is offline), the failure isn't surfaced to the
+/// caller: it's queued in `OFFLINE_QUEUE` and retried automatically once the connection comes
+/// back, so a join made while offline isn't lost.
+#[wasm_bindgen]
+pub async fn join_room(room_id: String) -> Result<(), JsValue> {
+    let storage = get_local_storage()?;
+    let user_id_b64 = storage
+        .get_item(STORAGE_KEY_USER_ID)
+        .map_err(|e| JsValue::from_str(&format!("Failed to read user ID: {:?}", e)))?
+        .ok_or_else(|| JsValue::from_str("User not initialized - call ensure_user first"))?;
+
+    if let Err(e) = create_membership(&ctx()?, &user_id_b64, &room_id).await {
+        warn!("join_room commit failed ({:?}), queuing for retry on reconnect", e);
+        OFFLINE_QUEUE.enqueue(Rc::new(move || {
+            let room_id = room_id.clone();
+            let user_id_b64 = user_id_b64.clone();
+            Box::pin(async move { create_membership(&ctx()?, &user_id_b64, &room_id).await })
+        }));
+    }
+
+    Ok(())
+}
+
+/// Redeem an invite token received out-of-band (e.g. pasted from an email) to complete
+/// first-time registration, for the case where the app was loaded without an `invite` URL
+/// query param. Errors if a user has already been registered on this device.
+#[wasm_bindgen]
+pub async fn redeem_invite(token: String) -> Result<(), JsValue> {
+    let storage = get_local_storage()?;
+    if storage
+        .get_item(STORAGE_KEY_USER_ID)
+        .map_err(|e| JsValue::from_str(&format!("Failed to read user ID: {:?}", e)))?
+        .is_some()
+    {
+        return Err(JsValue::from_str("User is already registered on this device"));
+    }
+
+    let user_view = init_user_internal(Some(token)).await?;
+    if let Some(user_signal) = CURRENT_USER.get() {
+        user_signal.set(JsValue::from(user_view.clone()));
+    }
+    Ok(())
+}
+
+/// An Admin context for the current device's user, for managing invitations. The server only
+/// honors it if this user was actually granted admin (see `UserKeyPairAgent::grant_admin`); a
+/// non-admin user gets the same result as `ctx()` since `check_request` re-derives the context
+/// from the signature rather than trusting this locally-asserted variant.
+#[wasm_bindgen]
+pub fn admin_ctx() -> Result<Context, JsValue> {
+    let storage = get_local_storage()?;
+    let user_id_b64 = storage
+        .get_item(STORAGE_KEY_USER_ID)
+        .map_err(|e| JsValue::from_str(&format!("Failed to read user ID: {:?}", e)))?
+        .ok_or_else(|| JsValue::from_str("User not initialized - call ensure_user first"))?;
+
+    let user_id = ankurah::proto::EntityId::from_base64(&user_id_b64)
+        .map_err(|e| JsValue::from_str(&format!("Invalid user ID: {}", e)))?;
+
+    get_node()
+        .context(MyContextData::Admin(user_id))
+        .map_err(|e| JsValue::from_str(&e.to_string()))
+}
+
+/// List every invitation, redeemed and revoked ones included, for an admin management screen.
+#[wasm_bindgen]
+pub async fn list_invitations() -> Result<Vec<JsValue>, JsValue> {
+    let context = admin_ctx()?;
+    let invites = context
+        .fetch::<InviteView>("true")
+        .await
+        .map_err(|e| JsValue::from_str(&format!("Failed to list invitations: {}", e)))?;
+    Ok(invites.into_iter().map(JsValue::from).collect())
+}
+
+/// Revoke an outstanding invitation by its `code_hash`, so it can no longer be redeemed.
+#[wasm_bindgen]
+pub async fn revoke_invitation(code_hash: String) -> Result<(), JsValue> {
+    let context = admin_ctx()?;
+    let matches = context
+        .fetch::<InviteView>(&format!("code_hash = '{}'", code_hash))
+        .await
+        .map_err(|e| JsValue::from_str(&format!("Failed to look up invitation: {}", e)))?;
+    let invite = matches
+        .into_iter()
+        .next()
+        .ok_or_else(|| JsValue::from_str("Invitation not found"))?;
+
+    let transaction = context.begin();
+    let invite_mut = transaction
+        .edit(&invite)
+        .await
+        .map_err(|e| JsValue::from_str(&format!("Failed to edit invitation: {}", e)))?;
+    invite_mut
+        .revoked()
+        .set(true)
+        .map_err(|e| JsValue::from_str(&format!("Failed to mark invitation revoked: {}", e)))?;
+    transaction
+        .commit()
+        .await
+        .map_err(|e| JsValue::from_str(&format!("Failed to commit revocation: {}", e)))?;
+
+    Ok(())
+}
+
+/// How long to wait for the server's background OPAQUE responder to answer a challenge before
+/// giving up (it polls every 200ms server-side; this gives it ample margin).
+const OPAQUE_CHALLENGE_TIMEOUT_MS: u32 = 10_000;
+const OPAQUE_CHALLENGE_POLL_INTERVAL_MS: u32 = 150;
+
+/// Opens an `OpaqueChallenge` with `request_bytes` and polls until the server's background
+/// responder fills in `response`, returning it decoded. See `ankurah_template_model::OpaqueChallenge`.
+async fn submit_opaque_challenge(
+    context: &Context,
+    handle: &str,
+    kind: &str,
+    request_bytes: Vec<u8>,
+) -> Result<Vec<u8>, JsValue> {
+    let transaction = context.begin();
+    let challenge_mut = transaction
+        .create(&OpaqueChallenge {
+            handle: handle.to_string(),
+            kind: kind.to_string(),
+            request: BASE64.encode(&request_bytes),
+            response: String::new(),
+        })
+        .await
+        .map_err(|e| JsValue::from_str(&format!("Failed to open OPAQUE challenge: {}", e)))?;
+    let challenge_id = challenge_mut.id();
+    transaction
+        .commit()
+        .await
+        .map_err(|e| JsValue::from_str(&format!("Failed to submit OPAQUE challenge: {}", e)))?;
+
+    let mut waited_ms = 0u32;
+    loop {
+        let challenge = context
+            .get::<OpaqueChallengeView>(challenge_id)
+            .await
+            .map_err(|e| JsValue::from_str(&format!("Failed to poll OPAQUE challenge: {}", e)))?;
+        let response = challenge
+            .response()
+            .map_err(|e| JsValue::from_str(&format!("Failed to read OPAQUE response: {}", e)))?;
+        if !response.is_empty() {
+            return BASE64
+                .decode(&response)
+                .map_err(|e| JsValue::from_str(&format!("Invalid OPAQUE response encoding: {}", e)));
+        }
+
+        if waited_ms >= OPAQUE_CHALLENGE_TIMEOUT_MS {
+            return Err(JsValue::from_str("Timed out waiting for server to answer OPAQUE challenge"));
+        }
+        connection::sleep_ms(OPAQUE_CHALLENGE_POLL_INTERVAL_MS).await;
+        waited_ms += OPAQUE_CHALLENGE_POLL_INTERVAL_MS;
+    }
+}
+
+/// Enroll this device's existing signing-key seed for cross-device recovery under `handle` and
+/// `password`: runs an OPAQUE registration against the server, then uploads the seed AES-GCM-
+/// wrapped under the resulting `export_key`. Must be called while already logged in (`ctx()`);
+/// it recovers the *current* identity on a new device, it doesn't create one.
+#[wasm_bindgen]
+pub async fn register_recovery(handle: String, password: String) -> Result<(), JsValue> {
+    let context = ctx()?;
+    let storage = get_local_storage()?;
+    let user_id_b64 = storage
+        .get_item(STORAGE_KEY_USER_ID)
+        .map_err(|e| JsValue::from_str(&format!("Failed to read user ID: {:?}", e)))?
+        .ok_or_else(|| JsValue::from_str("User not initialized - call ensure_user first"))?;
+
+    let (client_state, request) = ankurah_template_model::opaque_auth::register_start(password.as_bytes())
+        .map_err(|e| JsValue::from_str(&format!("OPAQUE registration failed to start: {}", e)))?;
+    let response_bytes = submit_opaque_challenge(
+        &context,
+        &handle,
+        "register-start",
+        request.serialize().to_vec(),
+    )
+    .await?;
+    let response = RegistrationResponse::<TemplateCipherSuite>::deserialize(&response_bytes)
+        .map_err(|e| JsValue::from_str(&format!("Invalid OPAQUE registration response: {}", e)))?;
+
+    let finish = ankurah_template_model::opaque_auth::register_finish(client_state, password.as_bytes(), response)
+        .map_err(|e| JsValue::from_str(&format!("OPAQUE registration failed to finish: {}", e)))?;
+    let envelope_bytes = submit_opaque_challenge(
+        &context,
+        &handle,
+        "register-finish",
+        finish.message.serialize().to_vec(),
+    )
+    .await?;
+
+    let seed = USER_KEYPAIR
+        .get()
+        .ok_or_else(|| JsValue::from_str("User keypair not initialized"))?
+        .to_bytes();
+    let (ciphertext, iv) =
+        recovery::wrap_seed_with_export_key(&seed, finish.export_key.as_slice()).await?;
+
+    let transaction = context.begin();
+    transaction
+        .create(&IdentityRecovery {
+            user: user_id_b64,
+            handle,
+            envelope: BASE64.encode(envelope_bytes),
+            wrapped_seed: BASE64.encode(&ciphertext),
+            wrapped_seed_iv: BASE64.encode(iv),
+        })
+        .await
+        .map_err(|e| JsValue::from_str(&format!("Failed to store recovery record: {}", e)))?;
+    transaction
+        .commit()
+        .await
+        .map_err(|e| JsValue::from_str(&format!("Failed to commit recovery record: {}", e)))?;
+
+    Ok(())
+}
+
+/// Recovers this account's signing-key seed via OPAQUE login against `handle` and `password`,
+/// and persists it as this device's identity for the *next* page load (the current page's `Node`
+/// is already bound to whatever key `start()` booted with; call `window.location.reload()` after
+/// this resolves to actually start signing as the recovered user). A wrong password fails the
+/// OPAQUE login itself -- there's no ciphertext to even attempt decrypting with the wrong key.
+#[wasm_bindgen]
+pub async fn recover_identity(handle: String, password: String) -> Result<(), JsValue> {
+    let node = get_node();
+    // Recovery itself runs unauthenticated: this device doesn't have an identity yet.
+    let context = node
+        .context(MyContextData::Anonymous { invite_code: None })
+        .map_err(|e| JsValue::from_str(&e.to_string()))?;
+
+    let records = context
+        .fetch::<IdentityRecoveryView>(&format!("handle = '{}'", handle))
+        .await
+        .map_err(|e| JsValue::from_str(&format!("Failed to look up recovery record: {}", e)))?;
+    let record = records
+        .into_iter()
+        .next()
+        .ok_or_else(|| JsValue::from_str("No recovery record found for this handle"))?;
+
+    let (client_state, request) = ankurah_template_model::opaque_auth::login_start(password.as_bytes())
+        .map_err(|e| JsValue::from_str(&format!("OPAQUE login failed to start: {}", e)))?;
+    let response_bytes = submit_opaque_challenge(&context, &handle, "login-start", request.serialize().to_vec()).await?;
+    let response = CredentialResponse::<TemplateCipherSuite>::deserialize(&response_bytes)
+        .map_err(|e| JsValue::from_str(&format!("Invalid OPAQUE login response: {}", e)))?;
+
+    let finish = ankurah_template_model::opaque_auth::login_finish(client_state, password.as_bytes(), response)
+        .map_err(|e| JsValue::from_str(&format!("Wrong password or corrupted login response: {}", e)))?;
+    submit_opaque_challenge(&context, &handle, "login-finish", finish.message.serialize().to_vec()).await?;
+
+    let envelope = record
+        .envelope()
+        .map_err(|e| JsValue::from_str(&format!("Failed to read recovery record: {}", e)))?;
+    if envelope.is_empty() {
+        return Err(JsValue::from_str("Recovery record has no registration envelope"));
+    }
+
+    let ciphertext = BASE64
+        .decode(record.wrapped_seed().map_err(|e| JsValue::from_str(&e.to_string()))?)
+        .map_err(|e| JsValue::from_str(&format!("Invalid wrapped seed encoding: {}", e)))?;
+    let iv_bytes = BASE64
+        .decode(record.wrapped_seed_iv().map_err(|e| JsValue::from_str(&e.to_string()))?)
+        .map_err(|e| JsValue::from_str(&format!("Invalid wrapped seed IV encoding: {}", e)))?;
+    let iv: [u8; 12] = iv_bytes
+        .try_into()
+        .map_err(|_| JsValue::from_str("Invalid wrapped seed IV length"))?;
+
+    let seed =
+        recovery::unwrap_seed_with_export_key(&ciphertext, &iv, finish.export_key.as_slice()).await?;
+
+    let owner = record
+        .user()
+        .map_err(|e| JsValue::from_str(&format!("Failed to read recovery record owner: {}", e)))?;
+    let owner_id = ankurah::proto::EntityId::from_base64(&owner)
+        .map_err(|e| JsValue::from_str(&format!("Invalid owner ID in recovery record: {}", e)))?;
+
+    // `NODE`'s `UserKeyPairAgent` was already built in `start()` around this device's own
+    // (freshly-generated) signing key, and that's baked into the `Node`/`Client` variant for the
+    // lifetime of this page load -- there's no live swap. Instead we overwrite the same
+    // localStorage slots `start()` reads on boot with the recovered identity, so a reload picks
+    // it up as if this had been the device's key all along; `register_recovery` from another
+    // device remains the one that actually wrote these bytes.
+    let storage = get_local_storage()?;
+    storage
+        .set_item(STORAGE_KEY_USER_ID, &owner_id.to_base64())
+        .map_err(|e| JsValue::from_str(&format!("Failed to store user ID: {:?}", e)))?;
+    let (wrapped_ciphertext, wrapped_iv) = secure_key_storage::wrap_seed(&seed).await?;
+    storage
+        .set_item(STORAGE_KEY_WRAPPED_KEY, &BASE64.encode(&wrapped_ciphertext))
+        .map_err(|e| JsValue::from_str(&format!("Failed to store wrapped key: {:?}", e)))?;
+    storage
+        .set_item(STORAGE_KEY_WRAPPED_KEY_IV, &BASE64.encode(wrapped_iv))
+        .map_err(|e| JsValue::from_str(&format!("Failed to store wrapped key IV: {:?}", e)))?;
+
+    Ok(())
+}
+
+/// Enrolls a password for the *currently logged-in* user, so it can log back in from a fresh
+/// device with just `user_id` + this password (see `login_with_password`) instead of needing the
+/// wrapped device key `recover_identity` transfers. Runs an OPAQUE registration against the
+/// server addressed by this user's own id (rather than the caller-chosen `handle` used by
+/// `register_recovery`/`IdentityRecovery`) and stores the resulting envelope directly on
+/// `User.password_envelope`.
+#[wasm_bindgen]
+pub async fn register_password(password: String) -> Result<(), JsValue> {
+    let context = ctx()?;
+    let storage = get_local_storage()?;
+    let user_id_b64 = storage
+        .get_item(STORAGE_KEY_USER_ID)
+        .map_err(|e| JsValue::from_str(&format!("Failed to read user ID: {:?}", e)))?
+        .ok_or_else(|| JsValue::from_str("User not initialized - call ensure_user first"))?;
+
+    let (client_state, request) = ankurah_template_model::opaque_auth::register_start(password.as_bytes())
+        .map_err(|e| JsValue::from_str(&format!("OPAQUE registration failed to start: {}", e)))?;
+    let response_bytes = submit_opaque_challenge(
+        &context,
+        &user_id_b64,
+        "register-start",
+        request.serialize().to_vec(),
+    )
+    .await?;
+    let response = RegistrationResponse::<TemplateCipherSuite>::deserialize(&response_bytes)
+        .map_err(|e| JsValue::from_str(&format!("Invalid OPAQUE registration response: {}", e)))?;
+
+    let finish = ankurah_template_model::opaque_auth::register_finish(client_state, password.as_bytes(), response)
+        .map_err(|e| JsValue::from_str(&format!("OPAQUE registration failed to finish: {}", e)))?;
+    let envelope_bytes = submit_opaque_challenge(
+        &context,
+        &user_id_b64,
+        "register-finish",
+        finish.message.serialize().to_vec(),
+    )
+    .await?;
+
+    let user_id = ankurah::proto::EntityId::from_base64(&user_id_b64)
+        .map_err(|e| JsValue::from_str(&format!("Invalid user ID: {}", e)))?;
+    let user = context
+        .get::<UserView>(user_id)
+        .await
+        .map_err(|e| JsValue::from_str(&format!("Failed to fetch user: {}", e)))?;
+
+    let transaction = context.begin();
+    let user_mut = transaction
+        .edit(&user)
+        .await
+        .map_err(|e| JsValue::from_str(&format!("Failed to edit user: {}", e)))?;
+    user_mut
+        .password_envelope()
+        .set(&BASE64.encode(envelope_bytes))
+        .map_err(|e| JsValue::from_str(&format!("Failed to set password envelope: {}", e)))?;
+    transaction
+        .commit()
+        .await
+        .map_err(|e| JsValue::from_str(&format!("Failed to commit password enrollment: {}", e)))?;
+
+    Ok(())
+}
+
+/// Logs in as `user_id` using a password enrolled by `register_password`, from a fresh device with
+/// no prior state -- no wrapped seed to transfer, since the signing key is derived straight from
+/// the OPAQUE `export_key` (`opaque_auth::derive_signing_key`), the same way `recover_identity`'s
+/// recovered seed ultimately gets used. As with `recover_identity`, this only persists the
+/// identity to localStorage for the *next* page load (this page's `Node`/`Client` is already
+/// bound to whatever key `start()` booted with); call `window.location.reload()` after this
+/// resolves.
+#[wasm_bindgen]
+pub async fn login_with_password(user_id: String, password: String) -> Result<(), JsValue> {
+    let node = get_node();
+    // Login itself runs unauthenticated: this device doesn't have an identity yet.
+    let context = node
+        .context(MyContextData::Anonymous { invite_code: None })
+        .map_err(|e| JsValue::from_str(&e.to_string()))?;
+
+    let (client_state, request) = ankurah_template_model::opaque_auth::login_start(password.as_bytes())
+        .map_err(|e| JsValue::from_str(&format!("OPAQUE login failed to start: {}", e)))?;
+    let response_bytes =
+        submit_opaque_challenge(&context, &user_id, "login-start", request.serialize().to_vec()).await?;
+    let response = CredentialResponse::<TemplateCipherSuite>::deserialize(&response_bytes)
+        .map_err(|e| JsValue::from_str(&format!("Invalid OPAQUE login response: {}", e)))?;
+
+    let finish = ankurah_template_model::opaque_auth::login_finish(client_state, password.as_bytes(), response)
+        .map_err(|e| JsValue::from_str(&format!("Wrong password or corrupted login response: {}", e)))?;
+    submit_opaque_challenge(&context, &user_id, "login-finish", finish.message.serialize().to_vec()).await?;
+
+    let seed = ankurah_template_model::opaque_auth::derive_signing_key(finish.export_key.as_slice()).to_bytes();
+
+    let storage = get_local_storage()?;
+    storage
+        .set_item(STORAGE_KEY_USER_ID, &user_id)
+        .map_err(|e| JsValue::from_str(&format!("Failed to store user ID: {:?}", e)))?;
+    let (ciphertext, iv) = secure_key_storage::wrap_seed(&seed).await?;
+    storage
+        .set_item(STORAGE_KEY_WRAPPED_KEY, &BASE64.encode(&ciphertext))
+        .map_err(|e| JsValue::from_str(&format!("Failed to store wrapped key: {:?}", e)))?;
+    storage
+        .set_item(STORAGE_KEY_WRAPPED_KEY_IV, &BASE64.encode(iv))
+        .map_err(|e| JsValue::from_str(&format!("Failed to store wrapped key IV: {:?}", e)))?;
+
+    Ok(())
+}
+
+/// Builds a fully independent, capability-scoped session from a token minted by
+/// `UserKeyPairAgent::issue_scoped_token` (see the model crate) -- for delegated/bot access or a
+/// shareable read-only link. This never touches the primary device identity from `start()`: it
+/// opens its own storage and websocket connection under a `ScopedToken` agent that can only ever
+/// assert `MyContextData::Scoped`, so a leaked token grants only what its scopes allow, never the
+/// full user identity.
+#[wasm_bindgen]
+pub async fn scoped_ctx(token: String) -> Result<Context, JsValue> {
+    let token_bytes = BASE64
+        .decode(&token)
+        .map_err(|e| JsValue::from_str(&format!("Invalid token encoding: {}", e)))?;
+    let (user_id, scopes, expires_at) = ankurah_template_model::decode_scoped_token_claims(&token_bytes)
+        .map_err(JsValue::from_str)?;
+
+    let storage_engine = IndexedDBStorageEngine::open("ankurah_template_scoped")
+        .await
+        .map_err(|e| JsValue::from_str(&e.to_string()))?;
+    let node = Node::new(
+        Arc::new(storage_engine),
+        UserKeyPairAgent::new_scoped_token(token_bytes),
+    );
+
+    let window = window().ok_or_else(|| JsValue::from_str("No window available"))?;
+    let hostname = window
+        .location()
+        .hostname()
+        .map_err(|e| JsValue::from_str(&format!("Failed to get hostname: {:?}", e)))?;
+    let ws_url = format!("ws://{}:9797", hostname);
+    let client = connect_with_backoff(&node, &ws_url, &|_state| {}).await;
+    node.system.wait_system_ready().await;
+    SCOPED_CLIENTS.borrow_mut().push(client);
+
+    node.context(MyContextData::Scoped { user_id, scopes, expires_at })
+        .map_err(|e| JsValue::from_str(&e.to_string()))
+}
+
+/// Requests a full-identity bearer token for the current user from the server (minting one needs
+/// `UserKeyPairAgent::issue_token`, which only the server can call since it needs `token_secret`).
+/// Must be called while already logged in (`ctx()`). Relayed as a `TokenIssuance` create-and-poll,
+/// the same pattern `submit_opaque_challenge` uses for OPAQUE messages. Pass the returned token
+/// (together with the current user ID) to `token_ctx` on another device/tab.
+#[wasm_bindgen]
+pub async fn issue_token(scope: u8, ttl_secs: i64) -> Result<String, JsValue> {
+    let context = ctx()?;
+    let storage = get_local_storage()?;
+    let user_id_b64 = storage
+        .get_item(STORAGE_KEY_USER_ID)
+        .map_err(|e| JsValue::from_str(&format!("Failed to read user ID: {:?}", e)))?
+        .ok_or_else(|| JsValue::from_str("User not initialized - call ensure_user first"))?;
+
+    let transaction = context.begin();
+    let request_mut = transaction
+        .create(&TokenIssuance {
+            user: user_id_b64,
+            scope: scope as i64,
+            ttl_secs,
+            token: String::new(),
+        })
+        .await
+        .map_err(|e| JsValue::from_str(&format!("Failed to open token issuance request: {}", e)))?;
+    let request_id = request_mut.id();
+    transaction
+        .commit()
+        .await
+        .map_err(|e| JsValue::from_str(&format!("Failed to submit token issuance request: {}", e)))?;
+
+    let mut waited_ms = 0u32;
+    loop {
+        let request = context
+            .get::<TokenIssuanceView>(request_id)
+            .await
+            .map_err(|e| JsValue::from_str(&format!("Failed to poll token issuance request: {}", e)))?;
+        let token = request
+            .token()
+            .map_err(|e| JsValue::from_str(&format!("Failed to read issued token: {}", e)))?;
+        if !token.is_empty() {
+            return Ok(token);
+        }
+
+        if waited_ms >= OPAQUE_CHALLENGE_TIMEOUT_MS {
+            return Err(JsValue::from_str("Timed out waiting for server to issue token"));
+        }
+        connection::sleep_ms(OPAQUE_CHALLENGE_POLL_INTERVAL_MS).await;
+        waited_ms += OPAQUE_CHALLENGE_POLL_INTERVAL_MS;
+    }
+}
+
+/// Builds an independent session authenticated with a bearer token minted by `issue_token`, for a
+/// second device/tab that should assert the full identity of `user_id` without replaying this
+/// page's own signing key. Like `scoped_ctx`, this never touches the primary device identity from
+/// `start()`: it opens its own storage and websocket connection under a `Token` agent, whose
+/// bearer token is itself the server's proof of who's asking -- there's no local signing key
+/// behind it at all.
+#[wasm_bindgen]
+pub async fn token_ctx(user_id: String, token: String) -> Result<Context, JsValue> {
+    let user_id_decoded = ankurah::proto::EntityId::from_base64(&user_id)
+        .map_err(|e| JsValue::from_str(&format!("Invalid user ID: {}", e)))?;
+    let token_bytes = BASE64
+        .decode(&token)
+        .map_err(|e| JsValue::from_str(&format!("Invalid token encoding: {}", e)))?;
+
+    let storage_engine = IndexedDBStorageEngine::open("ankurah_template_token")
+        .await
+        .map_err(|e| JsValue::from_str(&e.to_string()))?;
+    let node = Node::new(
+        Arc::new(storage_engine),
+        UserKeyPairAgent::new_token(user_id_decoded, token_bytes),
+    );
+
+    let window = window().ok_or_else(|| JsValue::from_str("No window available"))?;
+    let hostname = window
+        .location()
+        .hostname()
+        .map_err(|e| JsValue::from_str(&format!("Failed to get hostname: {:?}", e)))?;
+    let ws_url = format!("ws://{}:9797", hostname);
+    let client = connect_with_backoff(&node, &ws_url, &|_state| {}).await;
+    node.system.wait_system_ready().await;
+    SCOPED_CLIENTS.borrow_mut().push(client);
+
+    node.context(MyContextData::User(user_id_decoded))
+        .map_err(|e| JsValue::from_str(&e.to_string()))
+}
+
 // Just export the models and basic primitives
 // All business logic should be in the React app
@@ -0,0 +1,89 @@
+//! AES-GCM wrap/unwrap of the signing-key seed under an OPAQUE `export_key`, for
+//! `register_recovery`/`recover_identity`.
+//!
+//! Unlike `secure_key_storage` (which wraps the seed under a non-extractable key so it survives
+//! only on this device), the key here *is* the 32-byte `export_key` opaque-ke derives from the
+//! user's password -- it never touches IndexedDB, it's held only long enough to encrypt or
+//! decrypt, and it's identical on every device that completes the same OPAQUE login.
+
+use js_sys::{Object, Reflect, Uint8Array};
+use rand::{rngs::OsRng, RngCore};
+use sha2::{Digest, Sha256};
+use wasm_bindgen::JsValue;
+use wasm_bindgen_futures::JsFuture;
+use web_sys::window;
+
+/// `export_key` is 64 bytes (opaque-ke's `Hash` for `TemplateCipherSuite` is `Sha512`), but
+/// WebCrypto's AES-GCM raw import only accepts 16/24/32-byte keys -- hash it down to 32 bytes
+/// first, the same fix `opaque_auth::derive_signing_key` applies for the same reason.
+async fn import_aes_gcm_key(export_key: &[u8]) -> Result<web_sys::CryptoKey, JsValue> {
+    let subtle = window()
+        .ok_or_else(|| JsValue::from_str("No window available"))?
+        .crypto()?
+        .subtle();
+
+    let algorithm = Object::new();
+    Reflect::set(&algorithm, &"name".into(), &"AES-GCM".into())?;
+    let usages = js_sys::Array::of2(&"encrypt".into(), &"decrypt".into());
+
+    let key_bytes = Uint8Array::from(Sha256::digest(export_key).as_slice());
+    let key_promise = subtle.import_key_with_object(
+        "raw",
+        &key_bytes,
+        &algorithm,
+        false,
+        &usages,
+    )?;
+    Ok(wasm_bindgen::JsCast::unchecked_into(
+        JsFuture::from(key_promise).await?,
+    ))
+}
+
+/// Encrypts `seed` under `export_key`, returning `(ciphertext, iv)`.
+pub async fn wrap_seed_with_export_key(
+    seed: &[u8; 32],
+    export_key: &[u8],
+) -> Result<(Vec<u8>, [u8; 12]), JsValue> {
+    let key = import_aes_gcm_key(export_key).await?;
+    let subtle = window().unwrap().crypto()?.subtle();
+
+    let mut iv = [0u8; 12];
+    OsRng.fill_bytes(&mut iv);
+
+    let algorithm = Object::new();
+    Reflect::set(&algorithm, &"name".into(), &"AES-GCM".into())?;
+    Reflect::set(&algorithm, &"iv".into(), &Uint8Array::from(iv.as_slice()))?;
+
+    let plaintext = Uint8Array::from(seed.as_slice());
+    let ciphertext_buf =
+        JsFuture::from(subtle.encrypt_with_object_and_u8_array(&algorithm, &key, &mut plaintext.to_vec())?)
+            .await?;
+    let ciphertext = Uint8Array::new(&ciphertext_buf).to_vec();
+
+    Ok((ciphertext, iv))
+}
+
+/// Decrypts a seed previously produced by `wrap_seed_with_export_key`, given the same
+/// `export_key` (re-derived by completing an OPAQUE login with the right password).
+pub async fn unwrap_seed_with_export_key(
+    ciphertext: &[u8],
+    iv: &[u8; 12],
+    export_key: &[u8],
+) -> Result<[u8; 32], JsValue> {
+    let key = import_aes_gcm_key(export_key).await?;
+    let subtle = window().unwrap().crypto()?.subtle();
+
+    let algorithm = Object::new();
+    Reflect::set(&algorithm, &"name".into(), &"AES-GCM".into())?;
+    Reflect::set(&algorithm, &"iv".into(), &Uint8Array::from(iv.as_slice()))?;
+
+    let plaintext_buf = JsFuture::from(
+        subtle.decrypt_with_object_and_u8_array(&algorithm, &key, &mut ciphertext.to_vec())?,
+    )
+    .await?;
+    let plaintext = Uint8Array::new(&plaintext_buf).to_vec();
+
+    plaintext
+        .try_into()
+        .map_err(|_| JsValue::from_str("Unwrapped seed had unexpected length (wrong password?)"))
+}
@@ -0,0 +1,139 @@
+//! Wraps the user's Ed25519 signing key seed with a non-extractable WebCrypto AES-GCM key before
+//! it ever touches localStorage, so an injected script can read the ciphertext but never the key
+//! material itself (the wrapping key lives in IndexedDB as a non-extractable `CryptoKey`, which
+//! script can reference but not export).
+
+use js_sys::{Object, Promise, Reflect, Uint8Array};
+use rand::{rngs::OsRng, RngCore};
+use wasm_bindgen::{JsCast, JsValue};
+use wasm_bindgen_futures::JsFuture;
+use web_sys::{window, CryptoKey, IdbDatabase, IdbObjectStore, IdbRequest, IdbTransactionMode};
+
+const DB_NAME: &str = "ankurah_template_keystore";
+const STORE_NAME: &str = "wrapping_keys";
+const WRAPPING_KEY_RECORD: &str = "signing_key_wrapper";
+
+/// Turns an `IdbRequest` (event-based) into a `Future` by wiring `onsuccess`/`onerror` into a
+/// `Promise`, the same pattern WebCrypto's `subtle` Promise-based calls don't need but IndexedDB does.
+async fn request_result(request: IdbRequest) -> Result<JsValue, JsValue> {
+    let promise = Promise::new(&mut |resolve, reject| {
+        let req_ok = request.clone();
+        let on_success = wasm_bindgen::closure::Closure::once(move |_: web_sys::Event| {
+            let _ = resolve.call1(&JsValue::NULL, &req_ok.result().unwrap_or(JsValue::NULL));
+        });
+        let req_err = request.clone();
+        let on_error = wasm_bindgen::closure::Closure::once(move |_: web_sys::Event| {
+            let _ = reject.call1(&JsValue::NULL, &req_err.error().ok().into());
+        });
+        request.set_onsuccess(Some(on_success.as_ref().unchecked_ref()));
+        request.set_onerror(Some(on_error.as_ref().unchecked_ref()));
+        on_success.forget();
+        on_error.forget();
+    });
+    JsFuture::from(promise).await
+}
+
+async fn open_db() -> Result<IdbDatabase, JsValue> {
+    let window = window().ok_or_else(|| JsValue::from_str("No window available"))?;
+    let idb = window.indexed_db()?.ok_or_else(|| JsValue::from_str("IndexedDB not available"))?;
+    let open_request = idb.open_with_u32(DB_NAME, 1)?;
+
+    let store_name = STORE_NAME.to_string();
+    let on_upgrade = wasm_bindgen::closure::Closure::once({
+        let open_request = open_request.clone();
+        move |_: web_sys::Event| {
+            if let Ok(result) = open_request.result() {
+                let db: IdbDatabase = result.unchecked_into();
+                if !db.object_store_names().contains(&store_name) {
+                    let _ = db.create_object_store(&store_name);
+                }
+            }
+        }
+    });
+    open_request.set_onupgradeneeded(Some(on_upgrade.as_ref().unchecked_ref()));
+    on_upgrade.forget();
+
+    let result = request_result(open_request.into()).await?;
+    Ok(result.unchecked_into())
+}
+
+fn object_store(db: &IdbDatabase, mode: IdbTransactionMode) -> Result<IdbObjectStore, JsValue> {
+    let transaction = db.transaction_with_str_and_mode(STORE_NAME, mode)?;
+    transaction.object_store(STORE_NAME)
+}
+
+/// Fetch the stored non-extractable wrapping key from IndexedDB, generating and persisting a new
+/// AES-GCM 256 key on first run. The key itself is never readable from JS (`extractable: false`);
+/// only this process can use it via `subtle.encrypt`/`subtle.decrypt`.
+async fn get_or_create_wrapping_key() -> Result<CryptoKey, JsValue> {
+    let db = open_db().await?;
+
+    if let Ok(existing) = object_store(&db, IdbTransactionMode::Readonly) {
+        let get_request = existing.get(&JsValue::from_str(WRAPPING_KEY_RECORD))?;
+        if let Ok(value) = request_result(get_request).await {
+            if !value.is_undefined() && !value.is_null() {
+                return Ok(value.unchecked_into());
+            }
+        }
+    }
+
+    let subtle = window()
+        .ok_or_else(|| JsValue::from_str("No window available"))?
+        .crypto()?
+        .subtle();
+
+    let algorithm = Object::new();
+    Reflect::set(&algorithm, &"name".into(), &"AES-GCM".into())?;
+    Reflect::set(&algorithm, &"length".into(), &256.into())?;
+    let usages = js_sys::Array::of2(&"encrypt".into(), &"decrypt".into());
+
+    let key_promise = subtle.generate_key_with_object(&algorithm, false, &usages)?;
+    let key: CryptoKey = JsFuture::from(key_promise).await?.unchecked_into();
+
+    let store = object_store(&db, IdbTransactionMode::Readwrite)?;
+    store.put_with_key(&key, &JsValue::from_str(WRAPPING_KEY_RECORD))?;
+
+    Ok(key)
+}
+
+/// Encrypts `seed` with the IndexedDB-backed wrapping key, returning `(ciphertext, iv)` ready to
+/// persist in localStorage as base64 (the ciphertext is useless without the non-extractable key).
+pub async fn wrap_seed(seed: &[u8; 32]) -> Result<(Vec<u8>, [u8; 12]), JsValue> {
+    let key = get_or_create_wrapping_key().await?;
+    let subtle = window().unwrap().crypto()?.subtle();
+
+    let mut iv = [0u8; 12];
+    OsRng.fill_bytes(&mut iv);
+
+    let algorithm = Object::new();
+    Reflect::set(&algorithm, &"name".into(), &"AES-GCM".into())?;
+    Reflect::set(&algorithm, &"iv".into(), &Uint8Array::from(iv.as_slice()))?;
+
+    let plaintext = Uint8Array::from(seed.as_slice());
+    let ciphertext_buf =
+        JsFuture::from(subtle.encrypt_with_object_and_u8_array(&algorithm, &key, &mut plaintext.to_vec())?)
+            .await?;
+    let ciphertext = Uint8Array::new(&ciphertext_buf).to_vec();
+
+    Ok((ciphertext, iv))
+}
+
+/// Decrypts a seed previously produced by `wrap_seed` using the same IndexedDB-backed key.
+pub async fn unwrap_seed(ciphertext: &[u8], iv: &[u8; 12]) -> Result<[u8; 32], JsValue> {
+    let key = get_or_create_wrapping_key().await?;
+    let subtle = window().unwrap().crypto()?.subtle();
+
+    let algorithm = Object::new();
+    Reflect::set(&algorithm, &"name".into(), &"AES-GCM".into())?;
+    Reflect::set(&algorithm, &"iv".into(), &Uint8Array::from(iv.as_slice()))?;
+
+    let plaintext_buf = JsFuture::from(
+        subtle.decrypt_with_object_and_u8_array(&algorithm, &key, &mut ciphertext.to_vec())?,
+    )
+    .await?;
+    let plaintext = Uint8Array::new(&plaintext_buf).to_vec();
+
+    plaintext
+        .try_into()
+        .map_err(|_| JsValue::from_str("Unwrapped seed had unexpected length"))
+}